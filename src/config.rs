@@ -0,0 +1,196 @@
+use crate::criteria::Criteria;
+use crate::dim::Dimming;
+use crate::exec::Exec;
+use crate::hooks::{Action, Commands, Rule};
+use crate::layout::{Alternating, Layout};
+use crate::rename::{RenameRule, Renaming};
+use crate::scratchpad::Scratchpad;
+use crate::swallow::Swallowing;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A hook. In the config file a hook can be written either as a single sway
+/// command, as a list of commands which are joined with `;`, eg:
+///
+/// on-window-focus = [
+///   "[tiling] opacity 0.8",
+///   "[app_id=\"firefox\"] opacity 1",
+///   "opacity 1",
+/// ]
+///
+/// as a program to run, see `Exec`, or as rules deciding what to run, see `HookRule`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Hook {
+    Command(String),
+    Commands(Vec<String>),
+    Exec(Exec),
+    Rules(Vec<HookRule>),
+}
+
+/// Runs a hook only for windows, workspaces or outputs matching the criteria.
+/// The first matching rule wins, eg:
+///
+/// [[on-window-focus]]
+/// match = { app-id = "firefox" }
+/// run = "opacity 1"
+///
+/// [[on-window-focus]]
+/// run = "opacity 0.9"
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HookRule {
+    #[serde(rename = "match", default)]
+    pub criteria: Criteria,
+    pub run: Hook,
+}
+
+impl TryFrom<Hook> for Action {
+    type Error = anyhow::Error;
+
+    fn try_from(hook: Hook) -> Result<Self> {
+        match hook {
+            Hook::Command(cmd) => Ok(Action::Sway(Commands::parse(cmd)?)),
+            Hook::Commands(cmds) => Ok(Action::Sway(Commands::parse(cmds.join("; "))?)),
+            Hook::Exec(exec) => Ok(Action::Exec(exec)),
+            Hook::Rules(rules) => Ok(Action::Rules(
+                rules
+                    .into_iter()
+                    .map(|rule| {
+                        Ok(Rule {
+                            criteria: rule.criteria,
+                            action: Action::try_from(rule.run)?,
+                        })
+                    })
+                    .collect::<Result<_>>()?,
+            )),
+        }
+    }
+}
+
+/// The contents of the config file. Every field is optional so that the file
+/// and the command line can be merged, see `Config::merge`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub autolayout: Option<bool>,
+    pub layout: Option<Layout>,
+    pub workspace_layouts: Option<BTreeMap<String, Layout>>,
+    pub output_layouts: Option<BTreeMap<String, Layout>>,
+    pub autolayout_exclude: Option<Vec<Criteria>>,
+    pub workspace_renaming: Option<bool>,
+    pub dim: Option<bool>,
+    pub swallow: Option<bool>,
+    pub on_window_focus: Option<Hook>,
+    pub on_window_focus_leave: Option<Hook>,
+    pub on_window_new: Option<Hook>,
+    pub on_window_close: Option<Hook>,
+    pub on_window_move: Option<Hook>,
+    pub on_window_floating: Option<Hook>,
+    pub on_window_fullscreen_mode: Option<Hook>,
+    pub on_window_title: Option<Hook>,
+    pub on_window_urgent: Option<Hook>,
+    pub on_window_mark: Option<Hook>,
+    pub on_workspace_focus: Option<Hook>,
+    pub on_workspace_init: Option<Hook>,
+    pub on_workspace_empty: Option<Hook>,
+    pub on_workspace_move: Option<Hook>,
+    pub on_workspace_rename: Option<Hook>,
+    pub on_workspace_urgent: Option<Hook>,
+    pub on_output_added: Option<Hook>,
+    pub on_output_removed: Option<Hook>,
+    pub on_mode_change: Option<Hook>,
+    pub on_bar_config_update: Option<Hook>,
+    pub on_binding: Option<Hook>,
+    pub on_tick: Option<Hook>,
+    pub on_exit: Option<Hook>,
+    pub validate_hooks: Option<bool>,
+    pub max_hook_processes: Option<usize>,
+    pub renaming: Option<Renaming>,
+    pub alternating: Option<Alternating>,
+    pub dimming: Option<Dimming>,
+    pub swallowing: Option<Swallowing>,
+    pub rename_rules: Option<Vec<RenameRule>>,
+    pub focus_history_size: Option<usize>,
+    /// In milliseconds.
+    pub switch_timeout: Option<u64>,
+    pub wait_for_sway: Option<bool>,
+    pub scratchpads: Option<BTreeMap<String, Scratchpad>>,
+}
+
+impl Config {
+    /// Reads the config file at `path`. A missing file is the same as an empty one.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        let contents = fs::read_to_string(path)?;
+        toml::from_str(&contents).map_err(|e| anyhow!("{}: {}", path.display(), e))
+    }
+
+    /// Fills in everything not set in `self` from `other`, so values in `self` win.
+    pub fn merge(self, other: Config) -> Config {
+        Config {
+            autolayout: self.autolayout.or(other.autolayout),
+            layout: self.layout.or(other.layout),
+            workspace_layouts: self.workspace_layouts.or(other.workspace_layouts),
+            output_layouts: self.output_layouts.or(other.output_layouts),
+            autolayout_exclude: self.autolayout_exclude.or(other.autolayout_exclude),
+            workspace_renaming: self.workspace_renaming.or(other.workspace_renaming),
+            dim: self.dim.or(other.dim),
+            swallow: self.swallow.or(other.swallow),
+            on_window_focus: self.on_window_focus.or(other.on_window_focus),
+            on_window_focus_leave: self.on_window_focus_leave.or(other.on_window_focus_leave),
+            on_window_new: self.on_window_new.or(other.on_window_new),
+            on_window_close: self.on_window_close.or(other.on_window_close),
+            on_window_move: self.on_window_move.or(other.on_window_move),
+            on_window_floating: self.on_window_floating.or(other.on_window_floating),
+            on_window_fullscreen_mode: self
+                .on_window_fullscreen_mode
+                .or(other.on_window_fullscreen_mode),
+            on_window_title: self.on_window_title.or(other.on_window_title),
+            on_window_urgent: self.on_window_urgent.or(other.on_window_urgent),
+            on_window_mark: self.on_window_mark.or(other.on_window_mark),
+            on_workspace_focus: self.on_workspace_focus.or(other.on_workspace_focus),
+            on_workspace_init: self.on_workspace_init.or(other.on_workspace_init),
+            on_workspace_empty: self.on_workspace_empty.or(other.on_workspace_empty),
+            on_workspace_move: self.on_workspace_move.or(other.on_workspace_move),
+            on_workspace_rename: self.on_workspace_rename.or(other.on_workspace_rename),
+            on_workspace_urgent: self.on_workspace_urgent.or(other.on_workspace_urgent),
+            on_output_added: self.on_output_added.or(other.on_output_added),
+            on_output_removed: self.on_output_removed.or(other.on_output_removed),
+            on_mode_change: self.on_mode_change.or(other.on_mode_change),
+            on_bar_config_update: self.on_bar_config_update.or(other.on_bar_config_update),
+            on_binding: self.on_binding.or(other.on_binding),
+            on_tick: self.on_tick.or(other.on_tick),
+            on_exit: self.on_exit.or(other.on_exit),
+            validate_hooks: self.validate_hooks.or(other.validate_hooks),
+            max_hook_processes: self.max_hook_processes.or(other.max_hook_processes),
+            renaming: self.renaming.or(other.renaming),
+            alternating: self.alternating.or(other.alternating),
+            dimming: self.dimming.or(other.dimming),
+            swallowing: self.swallowing.or(other.swallowing),
+            rename_rules: self.rename_rules.or(other.rename_rules),
+            focus_history_size: self.focus_history_size.or(other.focus_history_size),
+            switch_timeout: self.switch_timeout.or(other.switch_timeout),
+            wait_for_sway: self.wait_for_sway.or(other.wait_for_sway),
+            scratchpads: self.scratchpads.or(other.scratchpads),
+        }
+    }
+}
+
+/// $XDG_CONFIG_HOME/persway/config.toml, falling back to ~/.config.
+pub fn default_config_path() -> Result<PathBuf> {
+    let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join(".config"))
+            .ok_or_else(|| anyhow!("Neither XDG_CONFIG_HOME nor HOME is set"))?,
+    };
+    Ok(config_dir.join("persway").join("config.toml"))
+}
@@ -0,0 +1,114 @@
+//! Connecting to sway, retrying with an increasing delay so persway survives
+//! sway dropping the IPC socket for a moment.
+use anyhow::{anyhow, Result};
+use async_std::future::Future;
+use async_std::os::unix::net::UnixStream;
+use async_std::task;
+use log::{debug, warn};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use swayipc_async::{Connection, Error, EventStream, EventType, Fallible};
+
+const MIN_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+/// Give up reconnecting after this long, sway is most likely gone for good.
+const RECONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+
+async fn retry<T, F, Fut>(what: &str, mut attempt: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Fallible<T>>,
+{
+    let started = Instant::now();
+    let mut backoff = MIN_BACKOFF;
+    loop {
+        match attempt().await {
+            Ok(value) => return Ok(value),
+            Err(e) if started.elapsed() < RECONNECT_TIMEOUT => {
+                warn!("{} err: {}, retrying in {:?}", what, e, backoff);
+                task::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+            Err(e) => {
+                return Err(anyhow!(
+                    "{} err: {}, giving up after {:?}",
+                    what,
+                    e,
+                    RECONNECT_TIMEOUT
+                ))
+            }
+        }
+    }
+}
+
+/// A connection for running commands and queries.
+pub async fn connect() -> Result<Connection> {
+    retry("sway connection", Connection::new).await
+}
+
+/// A connection subscribed to the given events.
+pub async fn subscribe(events: &[EventType]) -> Result<EventStream> {
+    retry("sway subscription", || async {
+        Connection::new().await?.subscribe(events).await
+    })
+    .await
+}
+
+/// The sway socket persway talks to, as far as the environment tells. Like
+/// swayipc, I3SOCK wins over SWAYSOCK.
+pub fn socket_path() -> Option<PathBuf> {
+    env::var_os("I3SOCK")
+        .or_else(|| env::var_os("SWAYSOCK"))
+        .map(PathBuf::from)
+}
+
+/// Looks for a sway accepting connections, other than the one at `gone`. Sway
+/// puts its socket in $XDG_RUNTIME_DIR, named after the user and its pid.
+pub async fn find_sway(gone: Option<&Path>) -> Result<Option<PathBuf>> {
+    let dir = env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(env::temp_dir);
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default();
+        if !name.starts_with("sway-ipc.") || !name.ends_with(".sock") || Some(&*path) == gone {
+            continue;
+        }
+        // a sway that crashed leaves its socket behind
+        if UnixStream::connect(&path).await.is_ok() {
+            return Ok(Some(path));
+        }
+    }
+    Ok(None)
+}
+
+/// Talks to the sway at `path` from now on.
+pub fn use_socket(path: &Path) {
+    env::set_var("SWAYSOCK", path);
+    if env::var_os("I3SOCK").is_some() {
+        env::set_var("I3SOCK", path);
+    }
+}
+
+/// Whether the error means the connection to sway is gone, rather than sway
+/// not liking what it was sent.
+pub fn is_disconnect(error: &anyhow::Error) -> bool {
+    matches!(error.downcast_ref::<Error>(), Some(Error::Io(_)))
+        || error.downcast_ref::<std::io::Error>().is_some()
+}
+
+/// Runs sway commands, logging them along with what sway made of them.
+pub async fn run_command<T: AsRef<str>>(
+    conn: &mut Connection,
+    cmd: T,
+) -> Result<Vec<Fallible<()>>> {
+    let cmd = cmd.as_ref();
+    let outcomes = conn.run_command(cmd).await?;
+    debug!("command: {} -> {:?}", cmd, outcomes);
+    Ok(outcomes)
+}
@@ -0,0 +1,363 @@
+use crate::daemon::Message;
+use crate::hooks;
+use crate::layout::{Layout, MasterCommand};
+use anyhow::{anyhow, Result};
+use async_std::channel::{self, Sender};
+use async_std::io::BufReader;
+use async_std::os::unix::net::{UnixListener, UnixStream};
+use async_std::prelude::*;
+use async_std::task;
+use log::warn;
+use std::env;
+use std::fs;
+use std::net::Shutdown;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// What the daemon sends back for a control command. The `Ok` body is
+/// written to the client as is, the `Err` message is prefixed with `error: `.
+pub type Reply = Result<String, String>;
+
+const ERROR_PREFIX: &str = "error: ";
+
+/// How to change a boolean setting.
+#[derive(Debug, Clone, Copy)]
+pub enum Switch {
+    On,
+    Off,
+    Toggle,
+}
+
+impl Switch {
+    pub fn apply(self, value: &mut bool) {
+        *value = match self {
+            Switch::On => true,
+            Switch::Off => false,
+            Switch::Toggle => !*value,
+        }
+    }
+}
+
+impl FromStr for Switch {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "" | "toggle" => Ok(Switch::Toggle),
+            "on" | "true" | "enable" => Ok(Switch::On),
+            "off" | "false" | "disable" => Ok(Switch::Off),
+            _ => Err(anyhow!("expected on, off or toggle, got '{}'", s)),
+        }
+    }
+}
+
+/// Which workspaces the `layout` command applies to.
+#[derive(Debug)]
+pub enum LayoutTarget {
+    FocusedWorkspace,
+    /// A workspace by number (or name, if it has no number).
+    Workspace(String),
+    /// Every workspace on an output, unless it has a layout of its own.
+    Output(String),
+}
+
+/// A command understood by the control socket. Commands are a single line,
+/// the first word names the command and the rest of the line is its argument.
+#[derive(Debug)]
+pub enum Command {
+    /// autolayout [on|off|toggle]
+    Autolayout(Switch),
+    /// layout [workspace <num>|output <name>] <none|alternating|master-stack|dwindle|spiral|three-column>,
+    /// the focused workspace when no workspace or output is given
+    Layout(LayoutTarget, Layout),
+    /// master <promote|swap|rotate <cw|ccw>|grow [ppt]|shrink [ppt]>, rearranges
+    /// the focused workspace when it is laid out as a master and a stack
+    Master(MasterCommand),
+    /// workspace-renaming [on|off|toggle]
+    WorkspaceRenaming(Switch),
+    /// dim [on|off|toggle]
+    Dim(Switch),
+    /// swallow [on|off|toggle]
+    Swallow(Switch),
+    /// <hook> [sway command], eg. on-window-focus, clears the hook when no command is given
+    Hook(&'static str, Option<String>),
+    /// focus-previous [workspace] [n], focuses the window that had focus n
+    /// (1 by default) windows ago, in the focused workspace only if asked to
+    FocusPrevious { workspace: bool, n: usize },
+    /// switch <next|prev> [workspace], focuses the next window back (or forward) in
+    /// the history each time, alt-tab style
+    Switch { forward: bool, workspace: bool },
+    /// switch end, ends switching right away instead of after the timeout
+    SwitchEnd,
+    /// history [workspace], lists the windows that had focus, most recent first
+    History { workspace: bool },
+    /// toggle <scratchpad>, shows or hides the window of a named scratchpad,
+    /// starting it when there is none
+    Toggle(String),
+    /// status
+    Status,
+}
+
+/// The arguments of the history commands, an optional `workspace` and a count.
+fn history_args(arg: &str) -> Result<(bool, Option<usize>)> {
+    let mut workspace = false;
+    let mut n = None;
+    for word in arg.split_whitespace() {
+        match word {
+            "workspace" => workspace = true,
+            _ => {
+                n = Some(
+                    word.parse()
+                        .map_err(|_| anyhow!("expected workspace or a number, got '{}'", word))?,
+                )
+            }
+        }
+    }
+    Ok((workspace, n))
+}
+
+impl FromStr for Command {
+    type Err = anyhow::Error;
+
+    fn from_str(line: &str) -> Result<Self> {
+        let line = line.trim();
+        let (name, arg) = line.split_once(' ').unwrap_or((line, ""));
+        let arg = arg.trim();
+        let hook = || Some(arg.to_string()).filter(|a| !a.is_empty());
+        match name {
+            "autolayout" => Ok(Command::Autolayout(arg.parse()?)),
+            "layout" => {
+                let (target, layout) = arg.rsplit_once(' ').unwrap_or(("", arg));
+                let target = match target.trim().split_once(' ') {
+                    None if target.is_empty() => LayoutTarget::FocusedWorkspace,
+                    Some(("workspace", num)) => LayoutTarget::Workspace(num.trim().to_string()),
+                    Some(("output", name)) => LayoutTarget::Output(name.trim().to_string()),
+                    _ => return Err(anyhow!("expected workspace <num> or output <name>")),
+                };
+                Ok(Command::Layout(target, layout.parse()?))
+            }
+            "master" => Ok(Command::Master(arg.parse()?)),
+            "workspace-renaming" => Ok(Command::WorkspaceRenaming(arg.parse()?)),
+            "dim" => Ok(Command::Dim(arg.parse()?)),
+            "swallow" => Ok(Command::Swallow(arg.parse()?)),
+            "focus-previous" => match history_args(arg)? {
+                (_, Some(0)) => Err(anyhow!("expected a number above 0")),
+                (workspace, n) => Ok(Command::FocusPrevious {
+                    workspace,
+                    n: n.unwrap_or(1),
+                }),
+            },
+            "switch" => {
+                let (step, arg) = arg.split_once(' ').unwrap_or((arg, ""));
+                let forward = match step {
+                    "next" => true,
+                    "prev" => false,
+                    "end" if arg.is_empty() => return Ok(Command::SwitchEnd),
+                    "end" => return Err(anyhow!("expected nothing after end")),
+                    _ => return Err(anyhow!("expected next, prev or end, got '{}'", step)),
+                };
+                match history_args(arg)? {
+                    (_, Some(_)) => Err(anyhow!("expected nothing or workspace")),
+                    (workspace, None) => Ok(Command::Switch { forward, workspace }),
+                }
+            }
+            "toggle" if arg.is_empty() => Err(anyhow!("expected a scratchpad name")),
+            "toggle" => Ok(Command::Toggle(arg.to_string())),
+            "history" => match history_args(arg)? {
+                (_, Some(_)) => Err(anyhow!("expected nothing or workspace")),
+                (workspace, None) => Ok(Command::History { workspace }),
+            },
+            "status" => Ok(Command::Status),
+            "" => Err(anyhow!("empty command")),
+            _ => match hooks::NAMES.iter().find(|n| **n == name) {
+                Some(hook_name) => Ok(Command::Hook(hook_name, hook())),
+                None => Err(anyhow!("unknown command '{}'", name)),
+            },
+        }
+    }
+}
+
+/// The default control socket lives in $XDG_RUNTIME_DIR and is named after a
+/// hash of $SWAYSOCK, so every sway session gets its own persway.
+pub fn default_socket_path() -> Result<PathBuf> {
+    let swaysock =
+        env::var_os("SWAYSOCK").ok_or_else(|| anyhow!("SWAYSOCK is not set, is sway running?"))?;
+    let runtime_dir = env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(env::temp_dir);
+    Ok(runtime_dir.join(format!("persway-{:x}.sock", fnv1a(swaysock.as_bytes()))))
+}
+
+/// The 64 bit FNV-1a hash, which unlike the hashers in std stays the same from
+/// one build of persway to the next, so `persway msg` finds a daemon started
+/// by another version.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ u64::from(*b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Binds the control socket, replacing a stale socket file left behind by a
+/// persway that did not exit cleanly.
+pub async fn bind(path: &Path) -> Result<UnixListener> {
+    if path.exists() {
+        if UnixStream::connect(path).await.is_ok() {
+            return Err(anyhow!(
+                "persway is already running, listening on {}",
+                path.display()
+            ));
+        }
+        fs::remove_file(path)?;
+    }
+    Ok(UnixListener::bind(path).await?)
+}
+
+pub async fn serve(listener: UnixListener, tx: Sender<Message>) {
+    let mut incoming = listener.incoming();
+    while let Some(stream) = incoming.next().await {
+        match stream {
+            Ok(stream) => {
+                let tx = tx.clone();
+                task::spawn(async move {
+                    if let Err(e) = handle_client(stream, tx).await {
+                        warn!("control client err: {}", e);
+                    }
+                });
+            }
+            Err(e) => warn!("control socket err: {}", e),
+        }
+    }
+}
+
+async fn handle_client(stream: UnixStream, tx: Sender<Message>) -> Result<()> {
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line).await?;
+    let reply = match line.parse::<Command>() {
+        Ok(command) => {
+            let (reply_tx, reply_rx) = channel::bounded(1);
+            tx.send(Message::Control(command, reply_tx)).await?;
+            reply_rx.recv().await?
+        }
+        Err(e) => Err(e.to_string()),
+    };
+    let response = match reply {
+        Ok(body) => body,
+        Err(e) => format!("{}{}\n", ERROR_PREFIX, e),
+    };
+    (&stream).write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+/// Sends a single command to a running persway and returns its reply.
+pub async fn send(path: &Path, command: &str) -> Result<String> {
+    let mut stream = UnixStream::connect(path)
+        .await
+        .map_err(|e| anyhow!("could not connect to {}: {}", path.display(), e))?;
+    stream
+        .write_all(format!("{}\n", command).as_bytes())
+        .await?;
+    stream.shutdown(Shutdown::Write)?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    match response.strip_prefix(ERROR_PREFIX) {
+        Some(e) => Err(anyhow!("{}", e.trim_end())),
+        None => Ok(response),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Command {
+        line.parse().unwrap()
+    }
+
+    #[test]
+    fn fnv1a_is_the_published_hash() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn layout_takes_the_last_word_as_the_layout() {
+        assert!(matches!(
+            parse("layout master-stack"),
+            Command::Layout(LayoutTarget::FocusedWorkspace, Layout::MasterStack)
+        ));
+        assert!(matches!(
+            parse("layout workspace 3 dwindle"),
+            Command::Layout(LayoutTarget::Workspace(num), Layout::Dwindle) if num == "3"
+        ));
+        assert!(matches!(
+            parse("layout  workspace 1: web  none "),
+            Command::Layout(LayoutTarget::Workspace(num), Layout::None) if num == "1: web"
+        ));
+        assert!(matches!(
+            parse("layout output DP-1 spiral"),
+            Command::Layout(LayoutTarget::Output(name), Layout::Spiral) if name == "DP-1"
+        ));
+    }
+
+    #[test]
+    fn layout_needs_a_known_layout_and_target() {
+        for line in [
+            "layout",
+            "layout tiling",
+            "layout workspace 3",
+            "layout output DP-1",
+            "layout screen 1 spiral",
+        ] {
+            assert!(line.parse::<Command>().is_err(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn switch_end_takes_nothing_else() {
+        assert!(matches!(parse("switch end"), Command::SwitchEnd));
+        assert!(matches!(
+            parse("switch prev workspace"),
+            Command::Switch {
+                forward: false,
+                workspace: true
+            }
+        ));
+        for line in ["switch", "switch end now", "switch next 2", "switch back"] {
+            assert!(line.parse::<Command>().is_err(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn focus_previous_counts_from_one() {
+        assert!(matches!(
+            parse("focus-previous"),
+            Command::FocusPrevious {
+                workspace: false,
+                n: 1
+            }
+        ));
+        assert!(matches!(
+            parse("focus-previous 3"),
+            Command::FocusPrevious {
+                workspace: false,
+                n: 3
+            }
+        ));
+        assert!(matches!(
+            parse("focus-previous 2 workspace"),
+            Command::FocusPrevious {
+                workspace: true,
+                n: 2
+            }
+        ));
+        for line in [
+            "focus-previous 0",
+            "focus-previous -1",
+            "focus-previous last",
+        ] {
+            assert!(line.parse::<Command>().is_err(), "{}", line);
+        }
+    }
+}
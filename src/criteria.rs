@@ -0,0 +1,88 @@
+use crate::tree;
+use regex::Regex;
+use serde::Deserialize;
+use std::convert::TryFrom;
+use swayipc_async::Node;
+
+/// A regular expression read from the config file. Like sway criteria the
+/// pattern is not anchored, use `^` and `$` to match the whole value.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Pattern(Regex);
+
+impl TryFrom<String> for Pattern {
+    type Error = regex::Error;
+
+    fn try_from(pattern: String) -> Result<Self, Self::Error> {
+        Regex::new(&pattern).map(Pattern)
+    }
+}
+
+impl Pattern {
+    fn is_match(&self, value: Option<&str>) -> bool {
+        value.is_some_and(|v| self.0.is_match(v))
+    }
+}
+
+/// Matches windows on their properties and where they are, eg. in the config file:
+///
+/// match = { app-id = "^org\\.mozilla\\.firefox$", floating = false }
+///
+/// Every given property has to match, no properties at all matches every window.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Criteria {
+    pub app_id: Option<Pattern>,
+    pub class: Option<Pattern>,
+    pub title: Option<Pattern>,
+    pub floating: Option<bool>,
+    /// The name of the workspace, as renamed by persway if it was. Renamed
+    /// workspaces keep their number in the name, eg. `^9(:|$)` for the default
+    /// name template.
+    pub workspace: Option<Pattern>,
+    /// The name of the output.
+    pub output: Option<Pattern>,
+}
+
+/// What criteria are matched against. Anything unknown does not match a
+/// criterion for it.
+#[derive(Debug, Default)]
+pub struct Subject<'a> {
+    pub app_id: Option<&'a str>,
+    pub class: Option<&'a str>,
+    pub title: Option<&'a str>,
+    pub floating: Option<bool>,
+    pub workspace: Option<&'a str>,
+    pub output: Option<&'a str>,
+}
+
+impl<'a> Subject<'a> {
+    pub fn window(node: &'a Node) -> Self {
+        Subject {
+            app_id: node.app_id.as_deref(),
+            class: node
+                .window_properties
+                .as_ref()
+                .and_then(|p| p.class.as_deref()),
+            title: node.name.as_deref(),
+            floating: Some(tree::is_floating(node)),
+            ..Subject::default()
+        }
+    }
+}
+
+impl Criteria {
+    pub fn matches(&self, subject: &Subject) -> bool {
+        let checks = [
+            (&self.app_id, subject.app_id),
+            (&self.class, subject.class),
+            (&self.title, subject.title),
+            (&self.workspace, subject.workspace),
+            (&self.output, subject.output),
+        ];
+        checks
+            .iter()
+            .all(|(pattern, value)| pattern.as_ref().is_none_or(|p| p.is_match(*value)))
+            && self.floating.is_none_or(|f| subject.floating == Some(f))
+    }
+}
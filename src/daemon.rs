@@ -0,0 +1,1126 @@
+use crate::config::Config;
+use crate::connection;
+use crate::control::{self, Command, LayoutTarget, Reply};
+use crate::criteria::{Criteria, Subject};
+use crate::dim::{Dimmer, Dimming};
+use crate::exec::{self, Spawner};
+use crate::history::{self, History};
+use crate::hooks::{self, Action, Commands, Context};
+use crate::layout::{self, Alternating, Layout};
+use crate::rename::{rename_workspace, OriginalNames, RenameRule, Renaming};
+use crate::scratchpad::{Scratchpad, Scratchpads};
+use crate::swallow::{Swallower, Swallowing};
+use crate::tree;
+use anyhow::{anyhow, Result};
+use async_std::channel::{self, Receiver, Sender};
+use async_std::future;
+use async_std::prelude::*;
+use async_std::task;
+use log::{debug, error, info, warn};
+use signal_hook::consts::signal::*;
+use signal_hook_async_std::Signals;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use swayipc_async::{
+    Connection, Error as SwayError, Event, EventStream, EventType, Fallible, Node, WindowChange,
+    WindowEvent, WorkspaceChange, WorkspaceEvent,
+};
+
+/// Everything the main loop reacts to. Sway events, control commands and
+/// signals all end up on the same channel so the loop is the only owner of
+/// the settings and the command connection.
+pub enum Message {
+    Event(Fallible<Event>),
+    /// The event subscription was lost and has been set up again.
+    Reconnected,
+    /// The event subscription was lost and could not be set up again.
+    Disconnected(anyhow::Error),
+    Control(Command, Sender<Reply>),
+    Signal(i32),
+}
+
+/// The runtime configuration of the daemon. It starts out from the command
+/// line merged with the config file and can be changed over the control socket.
+pub struct Settings {
+    pub autolayout: bool,
+    /// The layout of workspaces while autolayout is on.
+    pub layout: Layout,
+    /// Layouts by workspace number, these win over everything else.
+    pub workspace_layouts: BTreeMap<String, Layout>,
+    /// Layouts by output name, these win over the autolayout setting.
+    pub output_layouts: BTreeMap<String, Layout>,
+    /// Autolayout leaves workspaces alone while the focused window matches any of these.
+    pub autolayout_exclude: Vec<Criteria>,
+    pub workspace_renaming: bool,
+    /// Dim every window but the focused one.
+    pub dim: bool,
+    /// Let windows started from a terminal take its place.
+    pub swallow: bool,
+    /// Hooks by their name in the config file, eg. on-window-focus.
+    pub hooks: BTreeMap<&'static str, Action>,
+    /// Check hooks with sway before taking them on.
+    pub validate_hooks: bool,
+    /// How many exec hooks may run at the same time, more are skipped.
+    pub max_hook_processes: usize,
+    /// How many windows the focus history remembers.
+    pub focus_history_size: usize,
+    /// How long switching waits for the next step before it ends.
+    pub switch_timeout: Duration,
+    /// Wait for a new sway when sway shuts down, rather than exiting.
+    pub wait_for_sway: bool,
+    pub renaming: Renaming,
+    pub rename_rules: Vec<RenameRule>,
+    pub alternating: Alternating,
+    pub dimming: Dimming,
+    pub swallowing: Swallowing,
+    /// Named scratchpads, see `persway msg toggle`.
+    pub scratchpads: BTreeMap<String, Scratchpad>,
+}
+
+impl TryFrom<Config> for Settings {
+    type Error = anyhow::Error;
+
+    fn try_from(config: Config) -> Result<Self> {
+        let hooks = vec![
+            ("on-window-focus", config.on_window_focus),
+            ("on-window-focus-leave", config.on_window_focus_leave),
+            ("on-window-new", config.on_window_new),
+            ("on-window-close", config.on_window_close),
+            ("on-window-move", config.on_window_move),
+            ("on-window-floating", config.on_window_floating),
+            (
+                "on-window-fullscreen-mode",
+                config.on_window_fullscreen_mode,
+            ),
+            ("on-window-title", config.on_window_title),
+            ("on-window-urgent", config.on_window_urgent),
+            ("on-window-mark", config.on_window_mark),
+            ("on-workspace-focus", config.on_workspace_focus),
+            ("on-workspace-init", config.on_workspace_init),
+            ("on-workspace-empty", config.on_workspace_empty),
+            ("on-workspace-move", config.on_workspace_move),
+            ("on-workspace-rename", config.on_workspace_rename),
+            ("on-workspace-urgent", config.on_workspace_urgent),
+            ("on-output-added", config.on_output_added),
+            ("on-output-removed", config.on_output_removed),
+            ("on-mode-change", config.on_mode_change),
+            ("on-bar-config-update", config.on_bar_config_update),
+            ("on-binding", config.on_binding),
+            ("on-tick", config.on_tick),
+            ("on-exit", config.on_exit),
+        ];
+        let hooks = hooks
+            .into_iter()
+            .filter_map(|(name, hook)| Some((name, hook?)))
+            .map(|(name, hook)| {
+                let action = Action::try_from(hook).map_err(|e| anyhow!("{}: {}", name, e))?;
+                Ok((name, action))
+            })
+            .collect::<Result<_>>()?;
+        let alternating = config.alternating.unwrap_or_default();
+        alternating.check()?;
+        let dimming = config.dimming.unwrap_or_default();
+        dimming.check()?;
+        Ok(Settings {
+            autolayout: config.autolayout.unwrap_or(false),
+            layout: config.layout.unwrap_or_default(),
+            workspace_layouts: config.workspace_layouts.unwrap_or_default(),
+            output_layouts: config.output_layouts.unwrap_or_default(),
+            autolayout_exclude: config.autolayout_exclude.unwrap_or_default(),
+            workspace_renaming: config.workspace_renaming.unwrap_or(false),
+            dim: config.dim.unwrap_or(false),
+            swallow: config.swallow.unwrap_or(false),
+            wait_for_sway: config.wait_for_sway.unwrap_or(false),
+            hooks,
+            validate_hooks: config.validate_hooks.unwrap_or(false),
+            max_hook_processes: config
+                .max_hook_processes
+                .unwrap_or(exec::DEFAULT_MAX_PROCESSES),
+            renaming: config.renaming.unwrap_or_default(),
+            rename_rules: config.rename_rules.unwrap_or_default(),
+            alternating,
+            dimming,
+            swallowing: config.swallowing.unwrap_or_default(),
+            scratchpads: config.scratchpads.unwrap_or_default(),
+            focus_history_size: config.focus_history_size.unwrap_or(history::DEFAULT_SIZE),
+            switch_timeout: config
+                .switch_timeout
+                .map_or(history::DEFAULT_SWITCH_TIMEOUT, Duration::from_millis),
+        })
+    }
+}
+
+impl fmt::Display for Settings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let on_off = |b| if b { "on" } else { "off" };
+        writeln!(f, "autolayout: {}", on_off(self.autolayout))?;
+        writeln!(f, "layout: {}", self.layout)?;
+        let layouts = |layouts: &BTreeMap<String, Layout>| {
+            layouts
+                .iter()
+                .map(|(name, layout)| format!("{}={}", name, layout))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        writeln!(f, "workspace-layouts: {}", layouts(&self.workspace_layouts))?;
+        writeln!(f, "output-layouts: {}", layouts(&self.output_layouts))?;
+        writeln!(f, "autolayout-exclude: {}", self.autolayout_exclude.len())?;
+        writeln!(f, "workspace-renaming: {}", on_off(self.workspace_renaming))?;
+        writeln!(f, "dim: {}", on_off(self.dim))?;
+        writeln!(f, "swallow: {}", on_off(self.swallow))?;
+        for name in hooks::NAMES {
+            let hook = self.hooks.get(name).map(Action::to_string);
+            writeln!(f, "{}: {}", name, hook.unwrap_or_default())?;
+        }
+        writeln!(f, "validate-hooks: {}", on_off(self.validate_hooks))?;
+        writeln!(f, "max-hook-processes: {}", self.max_hook_processes)?;
+        writeln!(f, "rename-rules: {}", self.rename_rules.len())?;
+        let max_depth = self.alternating.max_depth.map(|d| d.to_string());
+        writeln!(
+            f,
+            "alternating: split-ratio={} min-width={} min-height={} small-layout={} max-depth={}",
+            self.alternating.split_ratio,
+            self.alternating.min_width,
+            self.alternating.min_height,
+            self.alternating.small_layout,
+            max_depth.as_deref().unwrap_or("none")
+        )?;
+        writeln!(
+            f,
+            "dimming: inactive-opacity={} active-opacity={} exclude={} dim-fullscreen={}",
+            self.dimming.inactive_opacity,
+            self.dimming.active_opacity,
+            self.dimming.exclude.len(),
+            self.dimming.dim_fullscreen
+        )?;
+        let scratchpads: Vec<&str> = self.scratchpads.keys().map(String::as_str).collect();
+        writeln!(f, "scratchpads: {}", scratchpads.join(" "))?;
+        writeln!(f, "focus-history-size: {}", self.focus_history_size)?;
+        writeln!(f, "switch-timeout: {:?}", self.switch_timeout)?;
+        writeln!(f, "wait-for-sway: {}", on_off(self.wait_for_sway))
+    }
+}
+
+impl Settings {
+    /// The number of a workspace, which stays the same when it is renamed.
+    fn workspace_num<'a>(&self, ws: &'a Node) -> &'a str {
+        self.renaming
+            .template
+            .num(ws.name.as_deref().unwrap_or_default())
+    }
+
+    /// Whether the focused window, or the focused workspace when it is empty,
+    /// matches an autolayout exclusion.
+    fn is_excluded(&self, tree: &Node) -> bool {
+        if self.autolayout_exclude.is_empty() {
+            return false;
+        }
+        let focused = match tree.find_focused_as_ref(|n| n.focused) {
+            Some(focused) => focused,
+            None => return false,
+        };
+        let ws = tree::workspace_of(tree, focused.id);
+        let output = ws.and_then(|ws| tree::output_of(tree, ws.id));
+        let subject = Subject {
+            workspace: ws.and_then(|ws| ws.name.as_deref()),
+            output: output.and_then(|o| o.name.as_deref()),
+            ..Subject::window(focused)
+        };
+        self.autolayout_exclude.iter().any(|c| c.matches(&subject))
+    }
+
+    /// The layout of a workspace on the given output. One set for the workspace
+    /// wins over one set for the output, which wins over the autolayout setting.
+    fn layout_for(&self, ws: &Node, output: &str) -> Layout {
+        let ws_layout = self.workspace_layouts.get(self.workspace_num(ws));
+        match ws_layout.or_else(|| self.output_layouts.get(output)) {
+            Some(layout) => *layout,
+            None if self.autolayout => self.layout,
+            None => Layout::None,
+        }
+    }
+
+    /// Checks every hook with sway if asked to, see `hooks::validate`.
+    async fn validate(&self, conn: &mut Connection) -> Result<()> {
+        if !self.validate_hooks {
+            return Ok(());
+        }
+        for (name, hook) in &self.hooks {
+            // programs are up to the user, sway can only say something about its own commands
+            for commands in hook.commands() {
+                hooks::validate(conn, name, &commands.render(&Context::sample())).await?;
+            }
+        }
+        Ok(())
+    }
+
+    fn has_layouts(&self) -> bool {
+        self.autolayout || !self.workspace_layouts.is_empty() || !self.output_layouts.is_empty()
+    }
+}
+
+struct Daemon {
+    /// Options given on the command line, these take precedence over the config file.
+    cli: Config,
+    config_path: PathBuf,
+    settings: Settings,
+    commands: Connection,
+    prev: Option<i64>,
+    history: History,
+    /// The active outputs, to tell which ones come and go.
+    outputs: Vec<String>,
+    spawner: Spawner,
+    dimmer: Dimmer,
+    original_names: OriginalNames,
+    scratchpads: Scratchpads,
+    swallower: Swallower,
+}
+
+/// How long restoring what persway changed may take before persway exits regardless.
+const RESTORE_TIMEOUT: Duration = Duration::from_secs(2);
+
+const SUBSCRIPTIONS: [EventType; 7] = [
+    EventType::Window,
+    EventType::Workspace,
+    EventType::Mode,
+    EventType::BarConfigUpdate,
+    EventType::Binding,
+    EventType::Shutdown,
+    EventType::Tick,
+];
+
+/// Why the main loop ended.
+enum Exit {
+    /// Persway was told to stop, or sway is gone and persway should go too.
+    Stop,
+    /// Sway is gone, persway waits for it to come back.
+    WaitForSway,
+}
+
+/// How often to look for a new sway while waiting for one.
+const SWAY_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Runs persway until it is told to stop. Without a socket path the control
+/// socket is named after the sway socket, which changes when sway is restarted.
+pub async fn run(cli: Config, config_path: PathBuf, socket_path: Option<PathBuf>) -> Result<()> {
+    let (tx, rx) = channel::unbounded();
+    let signals = Signals::new([SIGHUP, SIGINT, SIGQUIT, SIGTERM])?;
+    let handle = signals.handle();
+    let signals_task = task::spawn(forward_signals(signals, tx.clone()));
+
+    let mut result = Ok(());
+    loop {
+        match session(&cli, &config_path, socket_path.as_deref(), &tx, &rx).await {
+            Ok(Exit::WaitForSway) => {}
+            Ok(Exit::Stop) => break,
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        }
+        info!("waiting for sway to start");
+        match wait_for_sway(&rx).await {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        }
+    }
+
+    handle.close();
+    signals_task.await;
+    result
+}
+
+/// Runs persway for as long as one sway lives.
+async fn session(
+    cli: &Config,
+    config_path: &Path,
+    socket_path: Option<&Path>,
+    tx: &Sender<Message>,
+    rx: &Receiver<Message>,
+) -> Result<Exit> {
+    let settings = load_settings(cli, config_path)?;
+    let socket_path = match socket_path {
+        Some(path) => path.to_path_buf(),
+        None => control::default_socket_path()?,
+    };
+    let mut commands = connection::connect().await?;
+    settings.validate(&mut commands).await?;
+    let outputs = active_outputs(&mut commands).await?;
+    let history = History::new(settings.focus_history_size, settings.switch_timeout);
+
+    let listener = control::bind(&socket_path).await?;
+    info!("listening on {}", socket_path.display());
+    let server = task::spawn(control::serve(listener, tx.clone()));
+
+    let events = connection::subscribe(&SUBSCRIPTIONS).await?;
+    let forwarder = task::spawn(forward_events(events, tx.clone()));
+
+    let mut daemon = Daemon {
+        cli: cli.clone(),
+        config_path: config_path.to_path_buf(),
+        settings,
+        commands,
+        prev: None,
+        history,
+        outputs,
+        spawner: Spawner::default(),
+        dimmer: Dimmer::default(),
+        original_names: OriginalNames::default(),
+        scratchpads: Scratchpads::default(),
+        swallower: Swallower::default(),
+    };
+    let result = daemon.run(rx).await;
+
+    // neither should be heard from once this sway is gone
+    server.cancel().await;
+    forwarder.cancel().await;
+    fs::remove_file(&socket_path)?;
+    result
+}
+
+/// Waits until a new sway is up, or until persway is told to stop, which is
+/// when this returns false.
+async fn wait_for_sway(rx: &Receiver<Message>) -> Result<bool> {
+    let stops = |message: &Message| matches!(message, Message::Signal(SIGINT | SIGQUIT | SIGTERM));
+    let gone = connection::socket_path();
+    loop {
+        // whatever is left over from the sway that is gone is of no use to the next one
+        while let Ok(message) = rx.try_recv() {
+            if stops(&message) {
+                return Ok(false);
+            }
+        }
+        if let Some(path) = connection::find_sway(gone.as_deref()).await? {
+            info!("found sway at {}", path.display());
+            connection::use_socket(&path);
+            return Ok(true);
+        }
+        match future::timeout(SWAY_POLL_INTERVAL, rx.recv()).await {
+            Ok(Ok(message)) if stops(&message) => return Ok(false),
+            Ok(Err(_)) => return Ok(false),
+            // there is nothing to reload or to tell about without sway
+            Ok(Ok(_)) | Err(_) => {}
+        }
+    }
+}
+
+/// A window as listed in replies, eg. `94 firefox: Mozilla Firefox`.
+fn describe(node: &Node) -> String {
+    let class = node
+        .window_properties
+        .as_ref()
+        .and_then(|p| p.class.as_deref());
+    format!(
+        "{} {}: {}\n",
+        node.id,
+        node.app_id.as_deref().or(class).unwrap_or_default(),
+        node.name.as_deref().unwrap_or_default()
+    )
+}
+
+/// The names of the outputs that are switched on.
+async fn active_outputs(conn: &mut Connection) -> Result<Vec<String>> {
+    let outputs = conn.get_outputs().await?;
+    Ok(outputs
+        .into_iter()
+        .filter(|o| o.active)
+        .map(|o| o.name)
+        .collect())
+}
+
+fn load_settings(cli: &Config, config_path: &Path) -> Result<Settings> {
+    let config = Config::load(config_path)?;
+    Settings::try_from(cli.clone().merge(config))
+}
+
+async fn forward_signals(signals: Signals, tx: Sender<Message>) {
+    let mut signals = signals.fuse();
+    while let Some(signal) = signals.next().await {
+        if tx.send(Message::Signal(signal)).await.is_err() {
+            break;
+        }
+    }
+}
+
+async fn forward_events(mut events: EventStream, tx: Sender<Message>) {
+    loop {
+        while let Some(event) = events.next().await {
+            if let Err(SwayError::Io(e)) = event {
+                warn!("sway event connection lost: {}", e);
+                break;
+            }
+            if tx.send(Message::Event(event)).await.is_err() {
+                return;
+            }
+        }
+        let message = match connection::subscribe(&SUBSCRIPTIONS).await {
+            Ok(resubscribed) => {
+                events = resubscribed;
+                Message::Reconnected
+            }
+            Err(e) => Message::Disconnected(e),
+        };
+        let gave_up = matches!(message, Message::Disconnected(_));
+        if tx.send(message).await.is_err() || gave_up {
+            return;
+        }
+    }
+}
+
+impl Daemon {
+    async fn run(&mut self, rx: &Receiver<Message>) -> Result<Exit> {
+        self.dim().await;
+        while let Ok(message) = rx.recv().await {
+            match message {
+                Message::Event(Ok(Event::Shutdown(_))) => {
+                    info!("sway is shutting down");
+                    self.exit().await;
+                    return Ok(self.after_sway());
+                }
+                Message::Event(Ok(event)) => {
+                    debug!("event: {:?}", event);
+                    if let Err(e) = self.handle_event(event).await {
+                        self.recover(e).await?;
+                    }
+                }
+                Message::Event(Err(e)) => warn!("sway event err: {}", e),
+                Message::Reconnected => self.reconnect().await?,
+                Message::Disconnected(e) if self.settings.wait_for_sway => {
+                    error!("{}", e);
+                    return Ok(Exit::WaitForSway);
+                }
+                Message::Disconnected(e) => return Err(e),
+                Message::Control(command, reply) => {
+                    debug!("control: {:?}", command);
+                    // the client may have gone away, nothing to do about that
+                    let _ = reply.send(self.handle_control(command).await).await;
+                }
+                Message::Signal(SIGHUP) => match self.reload().await {
+                    Ok(()) => {
+                        info!("reloaded {}", self.config_path.display());
+                        self.dim().await;
+                    }
+                    Err(e) => error!("config reload err: {}", e),
+                },
+                Message::Signal(SIGINT | SIGQUIT | SIGTERM) => {
+                    self.exit().await;
+                    break;
+                }
+                Message::Signal(_) => unreachable!(),
+            }
+        }
+        Ok(Exit::Stop)
+    }
+
+    /// Cleans up after persway and runs the exit hook. Sway may be on its way
+    /// out, so failing to do so is only reported.
+    async fn exit(&mut self) {
+        self.restore().await;
+        if let Err(e) = self.run_hook(Context::new("on-exit"), None).await {
+            error!("on-exit hook err: {}", e);
+        }
+    }
+
+    fn after_sway(&self) -> Exit {
+        if self.settings.wait_for_sway {
+            Exit::WaitForSway
+        } else {
+            info!("sway is gone, exiting");
+            Exit::Stop
+        }
+    }
+
+    /// Re-reads the config file, keeping the current settings if it is no good.
+    async fn reload(&mut self) -> Result<()> {
+        let settings = load_settings(&self.cli, &self.config_path)?;
+        settings.validate(&mut self.commands).await?;
+        self.history
+            .configure(settings.focus_history_size, settings.switch_timeout);
+        self.settings = settings;
+        if !self.settings.workspace_renaming {
+            self.original_names.restore(&mut self.commands).await?;
+        }
+        Ok(())
+    }
+
+    /// Sets a hook over the control socket, checking it first if asked to.
+    async fn set_hook(&mut self, name: &'static str, hook: Option<String>) -> Result<()> {
+        match hook {
+            Some(hook) => {
+                let hook = Commands::parse(hook)?;
+                if self.settings.validate_hooks {
+                    let sample = hook.render(&Context::sample());
+                    hooks::validate(&mut self.commands, name, &sample).await?;
+                }
+                self.settings.hooks.insert(name, Action::Sway(hook));
+            }
+            None => {
+                self.settings.hooks.remove(name);
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_control(&mut self, command: Command) -> Reply {
+        let settings = &mut self.settings;
+        match command {
+            Command::Layout(target, layout) => {
+                let tree = self.commands.get_tree().await.map_err(|e| e.to_string())?;
+                let target = match target {
+                    LayoutTarget::FocusedWorkspace => {
+                        let ws = tree::focused_workspace(&tree).ok_or("No focused workspace")?;
+                        LayoutTarget::Workspace(settings.workspace_num(ws).to_string())
+                    }
+                    target => target,
+                };
+                match &target {
+                    LayoutTarget::Workspace(num) => {
+                        settings.workspace_layouts.insert(num.clone(), layout);
+                    }
+                    LayoutTarget::Output(name) => {
+                        settings.output_layouts.insert(name.clone(), layout);
+                    }
+                    LayoutTarget::FocusedWorkspace => unreachable!(),
+                }
+                // bring existing windows into shape on the workspaces that changed
+                self.arrange(&tree, Some(&target))
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+            Command::Autolayout(toggle) => toggle.apply(&mut settings.autolayout),
+            Command::WorkspaceRenaming(toggle) => {
+                toggle.apply(&mut settings.workspace_renaming);
+                if !settings.workspace_renaming {
+                    let restored = self.original_names.restore(&mut self.commands).await;
+                    restored.map_err(|e| e.to_string())?;
+                }
+            }
+            Command::Swallow(toggle) => toggle.apply(&mut settings.swallow),
+            Command::Dim(toggle) => {
+                toggle.apply(&mut settings.dim);
+                self.dim().await;
+            }
+            Command::Hook(name, cmd) => {
+                self.set_hook(name, cmd).await.map_err(|e| e.to_string())?
+            }
+            Command::FocusPrevious { workspace, n } => {
+                return self
+                    .focus_previous(workspace, n)
+                    .await
+                    .map_err(|e| e.to_string())
+            }
+            Command::Switch { forward, workspace } => {
+                return self
+                    .switch(forward, workspace)
+                    .await
+                    .map_err(|e| e.to_string())
+            }
+            Command::SwitchEnd => {
+                self.history.end_switch();
+                return Ok(String::new());
+            }
+            Command::Master(command) => {
+                let tree = self.commands.get_tree().await.map_err(|e| e.to_string())?;
+                let ws = tree::focused_workspace(&tree).ok_or("No focused workspace")?;
+                layout::master(command, &tree, ws, &mut self.commands)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                return Ok(String::new());
+            }
+            Command::Toggle(name) => {
+                let scratchpads = &self.settings.scratchpads;
+                self.scratchpads
+                    .toggle(&mut self.commands, scratchpads, &name)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                return Ok(String::new());
+            }
+            Command::History { workspace } => {
+                let tree = self.commands.get_tree().await.map_err(|e| e.to_string())?;
+                let windows = self.history_in(&tree, workspace);
+                return Ok(windows.into_iter().map(describe).collect());
+            }
+            Command::Status => {}
+        }
+        Ok(self.settings.to_string())
+    }
+
+    /// The windows in the focus history which are still there, on the focused
+    /// workspace only if asked to.
+    fn history_in<'a>(&mut self, tree: &'a Node, workspace: bool) -> Vec<&'a Node> {
+        let focused_ws = tree::focused_workspace(tree).map(|ws| ws.id);
+        self.history
+            .windows()
+            .filter_map(|id| tree.find_as_ref(|n| n.id == id))
+            .filter(|n| !workspace || tree::workspace_of(tree, n.id).map(|ws| ws.id) == focused_ws)
+            .collect()
+    }
+
+    async fn focus_previous(&mut self, workspace: bool, n: usize) -> Result<String> {
+        self.history.end_switch();
+        let tree = self.commands.get_tree().await?;
+        let windows = self.history_in(&tree, workspace);
+        let focused = tree.find_focused_as_ref(tree::is_window).map(|n| n.id);
+        // counting starts from the focused window, unless focus is on an empty workspace
+        let skip = usize::from(focused.is_some() && windows.first().map(|n| n.id) == focused);
+        let window = windows
+            .get(n - 1 + skip)
+            .ok_or_else(|| anyhow!("No window {} back in the focus history", n))?;
+        self.focus(window.id).await?;
+        Ok(describe(window))
+    }
+
+    async fn switch(&mut self, forward: bool, workspace: bool) -> Result<String> {
+        let tree = self.commands.get_tree().await?;
+        let windows = self.history_in(&tree, workspace);
+        let ids: Vec<i64> = windows.iter().map(|n| n.id).collect();
+        let id = self
+            .history
+            .switch(&ids, forward)
+            .ok_or_else(|| anyhow!("No other window in the focus history"))?;
+        self.focus(id).await?;
+        Ok(windows
+            .into_iter()
+            .find(|n| n.id == id)
+            .map(describe)
+            .unwrap_or_default())
+    }
+
+    /// Focuses a window, failing when sway refuses.
+    async fn focus(&mut self, id: i64) -> Result<()> {
+        let cmd = format!("[con_id={}] focus", id);
+        let outcomes = connection::run_command(&mut self.commands, cmd).await?;
+        match outcomes.into_iter().find_map(|outcome| outcome.err()) {
+            Some(e) => Err(anyhow!("could not focus window {}: {}", id, e)),
+            None => Ok(()),
+        }
+    }
+
+    /// Arranges the workspaces a layout command was for, or all of them.
+    async fn arrange(&mut self, tree: &Node, only: Option<&LayoutTarget>) -> Result<()> {
+        for (output, ws) in tree::workspaces(tree) {
+            let output = output.name.as_deref().unwrap_or_default();
+            let targeted = match only {
+                None => true,
+                Some(LayoutTarget::Workspace(num)) => self.settings.workspace_num(ws) == num,
+                Some(LayoutTarget::Output(name)) => output == name,
+                Some(LayoutTarget::FocusedWorkspace) => ws.focused,
+            };
+            if targeted {
+                let layout = self.settings.layout_for(ws, output);
+                layout.arrange(ws, &mut self.commands).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles an error from reacting to an event. Losing the connection to
+    /// sway is dealt with by reconnecting, anything else is only reported.
+    async fn recover(&mut self, e: anyhow::Error) -> Result<()> {
+        if !connection::is_disconnect(&e) {
+            error!("event err: {}", e);
+            return Ok(());
+        }
+        warn!("sway connection lost: {}", e);
+        self.reconnect().await
+    }
+
+    async fn reconnect(&mut self) -> Result<()> {
+        self.commands = connection::connect().await?;
+        info!("reconnected to sway");
+        self.resync().await
+    }
+
+    /// Catches up with whatever happened while persway was not connected.
+    async fn resync(&mut self) -> Result<()> {
+        let tree = self.commands.get_tree().await?;
+        self.prev = tree.find_focused_as_ref(tree::is_window).map(|n| n.id);
+        self.history
+            .retain(|id| tree.find_as_ref(|n| n.id == id).is_some());
+        if let Some(id) = self.prev {
+            self.history.focused(id);
+        }
+        self.update_outputs().await?;
+        self.dim().await;
+        if self.settings.has_layouts() {
+            self.arrange(&tree, None).await?;
+        }
+        Ok(())
+    }
+
+    async fn handle_event(&mut self, event: Event) -> Result<()> {
+        match event {
+            Event::Window(event) => self.handle_window_event(&event).await,
+            Event::Workspace(event) => self.handle_workspace_event(&event).await,
+            Event::Mode(event) => {
+                let context = Context {
+                    mode: Some(event.change),
+                    ..Context::new("on-mode-change")
+                };
+                self.run_hook(context, None).await
+            }
+            Event::BarConfigUpdate(event) => {
+                let context = Context {
+                    payload: Some(event.id.clone()),
+                    ..Context::new("on-bar-config-update")
+                };
+                self.run_hook(context, None).await
+            }
+            Event::Binding(event) => {
+                let context = Context {
+                    payload: Some(event.binding.command.clone()),
+                    ..Context::new("on-binding").change(&event.change)
+                };
+                self.run_hook(context, None).await
+            }
+            // sway sends one right away to every new subscriber
+            Event::Tick(event) if event.first => Ok(()),
+            Event::Tick(event) => {
+                let context = Context {
+                    payload: Some(event.payload),
+                    ..Context::new("on-tick")
+                };
+                self.run_hook(context, None).await
+            }
+            // not subscribed to
+            _ => Ok(()),
+        }
+    }
+
+    /// Runs a hook if it is set. Sway commands are scoped to a window if one is given.
+    async fn run_hook(&mut self, mut context: Context, scope: Option<i64>) -> Result<()> {
+        let name = context.hook;
+        let action = match self.settings.hooks.get(name) {
+            Some(action) => action.clone(),
+            None => return Ok(()),
+        };
+        if let Action::Rules(_) = action {
+            self.complete(&mut context).await?;
+        }
+        match action.select(&context.subject()).cloned() {
+            None => {}
+            Some(Action::Rules(_)) => unreachable!(),
+            Some(Action::Sway(hook)) => {
+                if hook.missing(&context) {
+                    self.complete(&mut context).await?;
+                }
+                let cmd = hook.render(&context);
+                let cmd = match scope {
+                    Some(id) => hooks::scoped(&cmd, id),
+                    None => cmd,
+                };
+                let label = format!("{} hook", name);
+                hooks::run(&mut self.commands, &label, &cmd).await?;
+            }
+            Some(Action::Exec(exec)) => {
+                self.complete(&mut context).await?;
+                let max = self.settings.max_hook_processes;
+                if let Err(e) = self.spawner.spawn(name, &exec, &context, max) {
+                    error!("{} hook err: {}", name, e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Fills in what the event did not tell about the window, its workspace and output.
+    async fn complete(&mut self, context: &mut Context) -> Result<()> {
+        let tree = self.commands.get_tree().await?;
+        if let Some(id) = context.con_id.filter(|_| context.title.is_none()) {
+            if let Some(node) = tree.find_as_ref(|n| n.id == id) {
+                context.set_window(node);
+            }
+        }
+        let ws = match &context.workspace {
+            Some(name) => tree::workspaces(&tree)
+                .into_iter()
+                .map(|(_, ws)| ws)
+                .find(|ws| ws.name.as_ref() == Some(name)),
+            // closed windows are gone from the tree, they were on the focused workspace
+            None => context
+                .con_id
+                .and_then(|id| tree::workspace_of(&tree, id))
+                .or_else(|| tree::focused_workspace(&tree)),
+        };
+        if let Some(ws) = ws {
+            context.workspace = ws.name.clone();
+            if context.output.is_none() {
+                context.output = tree::output_of(&tree, ws.id).and_then(|o| o.name.clone());
+            }
+        }
+        Ok(())
+    }
+
+    async fn rename_workspace(&mut self, window: &Node) {
+        let settings = &self.settings;
+        if !settings.workspace_renaming {
+            return;
+        }
+        if let Err(e) = rename_workspace(
+            window,
+            &mut self.commands,
+            &settings.renaming,
+            &settings.rename_rules,
+            &mut self.original_names,
+        )
+        .await
+        {
+            error!("workspace rename err: {}", e);
+        }
+    }
+
+    /// Undoes what persway did to sway: workspaces get their names back, dimmed
+    /// windows are made opaque and marks are removed. Window arrangement is left
+    /// as it is, there is no telling what it was before. Sway may be on its way
+    /// out, so this gives up after a while.
+    async fn restore(&mut self) {
+        let restore = async {
+            self.original_names.restore(&mut self.commands).await?;
+            self.dimmer.restore(&mut self.commands).await?;
+            self.swallower.restore(&mut self.commands).await?;
+            layout::unmark(&mut self.commands).await
+        };
+        match future::timeout(RESTORE_TIMEOUT, restore).await {
+            Ok(Ok(())) => debug!("restored workspace names and opacities"),
+            Ok(Err(e)) => error!("restore err: {}", e),
+            Err(_) => warn!("gave up restoring after {:?}", RESTORE_TIMEOUT),
+        }
+    }
+
+    /// Brings the opacity of windows in line with the dimming settings.
+    async fn dim(&mut self) {
+        let result = if self.settings.dim {
+            let dimming = &self.settings.dimming;
+            self.dimmer.update(&mut self.commands, dimming).await
+        } else {
+            self.dimmer.restore(&mut self.commands).await
+        };
+        if let Err(e) = result {
+            error!("dimming err: {}", e);
+        }
+    }
+
+    async fn handle_window_event(&mut self, event: &WindowEvent) -> Result<()> {
+        let mut captured = false;
+        match event.change {
+            WindowChange::Focus => {
+                if let Some(id) = self.prev {
+                    let context = Context {
+                        con_id: Some(id),
+                        ..Context::new("on-window-focus-leave")
+                    };
+                    self.run_hook(context, Some(id)).await?;
+                }
+                let context = Context {
+                    prev_con_id: self.prev,
+                    ..Context::window("on-window-focus", &event.container).change(&event.change)
+                };
+                self.run_hook(context, None).await?;
+                self.rename_workspace(&event.container).await;
+                self.prev = Some(event.container.id);
+                self.history.focused(event.container.id);
+            }
+            WindowChange::Close => {
+                if let Some(id) = self.prev {
+                    let context = Context {
+                        con_id: Some(id),
+                        ..Context::new("on-window-focus-leave")
+                    };
+                    self.run_hook(context, Some(id)).await?;
+                }
+                self.rename_workspace(&event.container).await;
+                self.prev = None;
+                self.history.remove(event.container.id);
+                self.scratchpads.forget(event.container.id);
+                let released = self
+                    .swallower
+                    .release(&mut self.commands, event.container.id)
+                    .await;
+                if let Err(e) = released {
+                    error!("swallowing err: {}", e);
+                }
+            }
+            WindowChange::New => {
+                let scratchpads = &self.settings.scratchpads;
+                let capture = self
+                    .scratchpads
+                    .capture(&mut self.commands, scratchpads, &event.container)
+                    .await;
+                captured = capture.unwrap_or_else(|e| {
+                    error!("scratchpad err: {}", e);
+                    false
+                });
+                if !captured && self.settings.swallow {
+                    let swallowing = &self.settings.swallowing;
+                    let swallowed = self
+                        .swallower
+                        .swallow(&mut self.commands, swallowing, &event.container)
+                        .await;
+                    if let Err(e) = swallowed {
+                        error!("swallowing err: {}", e);
+                    }
+                }
+            }
+            // only changes to what the name is made of, on the focused workspace
+            WindowChange::Title
+                if self.settings.workspace_renaming
+                    && event.container.focused
+                    && self.settings.renaming.renames_on(&event.change) =>
+            {
+                self.rename_workspace(&event.container).await;
+            }
+            WindowChange::Move
+                if self.settings.workspace_renaming
+                    && self.settings.renaming.renames_on(&event.change) =>
+            {
+                // the window may have moved away, name after the one with focus now
+                match self.commands.get_tree().await {
+                    Ok(tree) => {
+                        if let Some(focused) = tree.find_focused_as_ref(|n| n.focused) {
+                            self.rename_workspace(focused).await;
+                        }
+                    }
+                    Err(e) => error!("workspace rename err: {}", e),
+                }
+            }
+            _ => {}
+        }
+        if event.change == WindowChange::Move {
+            let moved = self
+                .swallower
+                .moved(&mut self.commands, event.container.id)
+                .await;
+            if let Err(e) = moved {
+                error!("swallowing err: {}", e);
+            }
+        }
+        if let Some(name) = hooks::window_hook(&event.change) {
+            // a closed window is gone by the time the hook runs, so there is nothing to scope to
+            let scope = Some(event.container.id).filter(|_| event.change != WindowChange::Close);
+            let context = Context::window(name, &event.container).change(&event.change);
+            self.run_hook(context, scope).await?;
+        }
+        let dims = match event.change {
+            WindowChange::Focus
+            | WindowChange::New
+            | WindowChange::Close
+            | WindowChange::Move
+            | WindowChange::Floating
+            | WindowChange::FullscreenMode => true,
+            WindowChange::Title => self.settings.dimming.by_title(),
+            _ => false,
+        };
+        if dims {
+            self.dim().await;
+        }
+
+        // scratchpad windows float, there is nothing to lay out
+        if captured {
+            return Ok(());
+        }
+        if let Err(e) = self.autolayout(event).await {
+            error!("autolayout err: {}", e);
+        };
+        Ok(())
+    }
+
+    async fn handle_workspace_event(&mut self, event: &WorkspaceEvent) -> Result<()> {
+        if let Some(name) = hooks::workspace_hook(&event.change) {
+            let context = Context {
+                workspace: event.current.as_ref().and_then(|ws| ws.name.clone()),
+                ..Context::new(name).change(&event.change)
+            };
+            self.run_hook(context, None).await?;
+        }
+        // focus on an empty workspace leaves no window focused, and moved
+        // workspaces may match other exclusions
+        if matches!(event.change, WorkspaceChange::Focus | WorkspaceChange::Move) {
+            self.dim().await;
+        }
+        // swayipc has no output events, but workspaces are created on new outputs
+        // and moved off of removed ones, so that is when to look for changes
+        if matches!(
+            event.change,
+            WorkspaceChange::Init
+                | WorkspaceChange::Empty
+                | WorkspaceChange::Move
+                | WorkspaceChange::Reload
+        ) {
+            self.update_outputs().await?;
+        }
+        Ok(())
+    }
+
+    /// Runs the output hooks for outputs which came or went since last time.
+    async fn update_outputs(&mut self) -> Result<()> {
+        let outputs = active_outputs(&mut self.commands).await?;
+        let added: Vec<String> = outputs
+            .iter()
+            .filter(|o| !self.outputs.contains(o))
+            .cloned()
+            .collect();
+        let removed: Vec<String> = self
+            .outputs
+            .iter()
+            .filter(|o| !outputs.contains(o))
+            .cloned()
+            .collect();
+        self.outputs = outputs;
+        for output in added {
+            info!("output added: {}", output);
+            let context = Context {
+                output: Some(output),
+                ..Context::new("on-output-added")
+            };
+            self.run_hook(context, None).await?;
+        }
+        for output in removed {
+            info!("output removed: {}", output);
+            let context = Context {
+                output: Some(output),
+                ..Context::new("on-output-removed")
+            };
+            self.run_hook(context, None).await?;
+        }
+        Ok(())
+    }
+
+    async fn autolayout(&mut self, event: &WindowEvent) -> Result<()> {
+        let settings = &self.settings;
+        if !settings.has_layouts() {
+            return Ok(());
+        }
+        if !matches!(
+            event.change,
+            WindowChange::Focus | WindowChange::New | WindowChange::Close | WindowChange::Move
+        ) {
+            return Ok(());
+        }
+        let tree = self.commands.get_tree().await?;
+        // closed windows are gone from the tree, they were on the focused workspace
+        let ws = tree::workspace_of(&tree, event.container.id)
+            .or_else(|| tree::focused_workspace(&tree))
+            .ok_or_else(|| anyhow!("No workspace"))?;
+        if ws.name.as_deref().unwrap_or_default().starts_with("__i3") {
+            return Ok(());
+        }
+        let output = tree::output_of(&tree, ws.id)
+            .and_then(|o| o.name.as_deref())
+            .unwrap_or_default();
+        if settings.is_excluded(&tree) {
+            return Ok(());
+        }
+        settings
+            .layout_for(ws, output)
+            .handle(event, &tree, ws, &settings.alternating, &mut self.commands)
+            .await
+    }
+}
@@ -0,0 +1,120 @@
+//! Dims every window but the focused one. Persway keeps track of the opacity
+//! it gave each window, so only windows whose opacity changes are sent a
+//! command, and it can put every window back the way it was.
+use crate::criteria::{Criteria, Subject};
+use crate::hooks;
+use crate::tree;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use swayipc_async::{Connection, Node};
+
+/// The opacity of windows sway has not been told otherwise about.
+const OPAQUE: f64 = 1.0;
+
+/// How windows are dimmed, the `[dimming]` table in the config file, eg:
+///
+/// [dimming]
+/// inactive-opacity = 0.8
+/// exclude = [{ app-id = "^mpv$" }, { class = "^Gimp" }]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Dimming {
+    /// The opacity of every window but the focused one.
+    pub inactive_opacity: f64,
+    /// The opacity of the focused window and of windows which are not dimmed.
+    pub active_opacity: f64,
+    /// Windows matching any of these are never dimmed.
+    pub exclude: Vec<Criteria>,
+    /// Dim fullscreen windows as well, by default they are left alone so
+    /// whatever is fullscreen on another output stays as it is.
+    pub dim_fullscreen: bool,
+}
+
+impl Default for Dimming {
+    fn default() -> Self {
+        Dimming {
+            inactive_opacity: 0.8,
+            active_opacity: OPAQUE,
+            exclude: vec![],
+            dim_fullscreen: false,
+        }
+    }
+}
+
+impl Dimming {
+    pub fn check(&self) -> Result<()> {
+        for (name, opacity) in [
+            ("inactive-opacity", self.inactive_opacity),
+            ("active-opacity", self.active_opacity),
+        ] {
+            if !(0.0..=1.0).contains(&opacity) {
+                return Err(anyhow!(
+                    "dimming: {} must be between 0 and 1, got {}",
+                    name,
+                    opacity
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the exclusions look at window titles, which change all the time.
+    pub fn by_title(&self) -> bool {
+        self.exclude.iter().any(|c| c.title.is_some())
+    }
+
+    fn opacity(&self, window: &Node, subject: &Subject) -> f64 {
+        let dim = !window.focused
+            && (self.dim_fullscreen || !tree::is_full_screen(window))
+            && !self.exclude.iter().any(|c| c.matches(subject));
+        if dim {
+            self.inactive_opacity
+        } else {
+            self.active_opacity
+        }
+    }
+}
+
+/// The opacities persway has set, by window.
+#[derive(Debug, Default)]
+pub struct Dimmer {
+    opacities: HashMap<i64, f64>,
+}
+
+impl Dimmer {
+    /// Gives every window in the tree the opacity it should have.
+    pub async fn update(&mut self, conn: &mut Connection, dimming: &Dimming) -> Result<()> {
+        let tree = conn.get_tree().await?;
+        let mut opacities = HashMap::new();
+        let mut cmds = vec![];
+        for (output, ws) in tree::workspaces(&tree) {
+            for window in tree::windows(ws) {
+                let subject = Subject {
+                    workspace: ws.name.as_deref(),
+                    output: output.name.as_deref(),
+                    ..Subject::window(window)
+                };
+                let opacity = dimming.opacity(window, &subject);
+                if self.opacities.get(&window.id) != Some(&opacity) {
+                    cmds.push(format!("[con_id={}] opacity {}", window.id, opacity));
+                }
+                opacities.insert(window.id, opacity);
+            }
+        }
+        // windows which are gone are forgotten along the way
+        self.opacities = opacities;
+        hooks::run(conn, "dimming", &cmds.join("; ")).await
+    }
+
+    /// Makes every window persway dimmed opaque again.
+    pub async fn restore(&mut self, conn: &mut Connection) -> Result<()> {
+        let cmds: Vec<String> = self
+            .opacities
+            .drain()
+            .filter(|(_, opacity)| *opacity != OPAQUE)
+            .map(|(id, _)| format!("[con_id={}] opacity {}", id, OPAQUE))
+            .collect();
+        hooks::run(conn, "dimming", &cmds.join("; ")).await
+    }
+}
@@ -0,0 +1,152 @@
+//! Hooks which run a program instead of sway commands. The program gets what
+//! the hook is about in `PERSWAY_*` environment variables, or as json on
+//! stdin. Programs run in the background, each watched by a task of its own,
+//! so a slow one cannot hold up persway.
+use crate::hooks::Context;
+use anyhow::Result;
+use async_std::task;
+use log::{debug, warn};
+use serde::Deserialize;
+use std::fmt;
+use std::io::Write;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+const DEFAULT_TIMEOUT: u64 = 10;
+/// How many hook programs may run at the same time, unless configured otherwise.
+pub const DEFAULT_MAX_PROCESSES: usize = 8;
+
+/// A program to run, either a shell command or a program and its arguments.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Program {
+    Shell(String),
+    Args(Vec<String>),
+}
+
+/// An exec hook, eg. in the config file:
+///
+/// on-window-focus = { exec = ["notify-focus", "--quiet"], json = true, timeout = 5 }
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Exec {
+    pub exec: Program,
+    /// Write the context as json to stdin rather than only setting environment variables.
+    #[serde(default)]
+    pub json: bool,
+    /// The program is killed after this many seconds.
+    pub timeout: Option<u64>,
+}
+
+impl fmt::Display for Exec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.exec {
+            Program::Shell(cmd) => write!(f, "exec {}", cmd)?,
+            Program::Args(args) => write!(f, "exec {}", args.join(" "))?,
+        }
+        if self.json {
+            write!(f, " (json)")?;
+        }
+        Ok(())
+    }
+}
+
+impl Exec {
+    fn command(&self) -> Option<Command> {
+        match &self.exec {
+            Program::Shell(cmd) => {
+                let mut command = Command::new("sh");
+                command.arg("-c").arg(cmd);
+                Some(command)
+            }
+            Program::Args(args) => {
+                let (program, args) = args.split_first()?;
+                let mut command = Command::new(program);
+                command.args(args);
+                Some(command)
+            }
+        }
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout.unwrap_or(DEFAULT_TIMEOUT))
+    }
+}
+
+/// Starts hook programs, as long as not too many of them are running already.
+#[derive(Debug, Clone, Default)]
+pub struct Spawner {
+    running: Arc<AtomicUsize>,
+}
+
+impl Spawner {
+    pub fn spawn(&self, name: &str, exec: &Exec, context: &Context, max: usize) -> Result<()> {
+        if self.running.load(Ordering::SeqCst) >= max {
+            warn!(
+                "{} hook skipped, {} hook programs are running already",
+                name, max
+            );
+            return Ok(());
+        }
+        let mut command = match exec.command() {
+            Some(command) => command,
+            None => {
+                warn!("{} hook has an empty exec", name);
+                return Ok(());
+            }
+        };
+        command.envs(context.env()).stdin(if exec.json {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        });
+        let mut child = command.spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            // the program may not care about its input, that is fine
+            let _ = writeln!(stdin, "{}", serde_json::to_string(context)?);
+        }
+        debug!("{} hook: started {} ({})", name, exec, child.id());
+        self.running.fetch_add(1, Ordering::SeqCst);
+        let running = self.running.clone();
+        let name = name.to_string();
+        let timeout = exec.timeout();
+        task::spawn(async move {
+            watch(&name, child, timeout).await;
+            running.fetch_sub(1, Ordering::SeqCst);
+        });
+        Ok(())
+    }
+}
+
+/// Waits for a hook program to exit, killing it when it takes too long.
+async fn watch(name: &str, mut child: Child, timeout: Duration) {
+    let started = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return,
+            Ok(Some(status)) => {
+                warn!("{} hook: program {} {}", name, child.id(), status);
+                return;
+            }
+            Ok(None) if started.elapsed() >= timeout => {
+                warn!(
+                    "{} hook: program {} took longer than {:?}, killing it",
+                    name,
+                    child.id(),
+                    timeout
+                );
+                let _ = child.kill();
+                let _ = child.wait();
+                return;
+            }
+            Ok(None) => task::sleep(POLL_INTERVAL).await,
+            Err(e) => {
+                warn!("{} hook: program {} err: {}", name, child.id(), e);
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How many windows the focus history remembers, unless configured otherwise.
+pub const DEFAULT_SIZE: usize = 100;
+/// How long a switch waits for the next step before it ends, unless configured otherwise.
+pub const DEFAULT_SWITCH_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Windows in the order they had focus, most recent first. There is one
+/// history for all workspaces, the history of a workspace is the part of it
+/// that is on the workspace.
+#[derive(Debug)]
+pub struct History {
+    windows: VecDeque<i64>,
+    size: usize,
+    switch: Option<Switch>,
+    switch_timeout: Duration,
+}
+
+/// Going through the history one window at a time, alt-tab style. The windows
+/// focused along the way do not count as focused until the switch ends, so the
+/// history stays put while switching. A switch ends when it is told to, when
+/// focus changes some other way or when no step follows within the timeout.
+#[derive(Debug)]
+struct Switch {
+    position: usize,
+    window: i64,
+    last_step: Instant,
+}
+
+impl History {
+    pub fn new(size: usize, switch_timeout: Duration) -> Self {
+        History {
+            windows: VecDeque::new(),
+            size,
+            switch: None,
+            switch_timeout,
+        }
+    }
+
+    pub fn configure(&mut self, size: usize, switch_timeout: Duration) {
+        self.size = size;
+        self.switch_timeout = switch_timeout;
+        self.windows.truncate(size);
+    }
+
+    /// Moves a window to the front. Focus given by switching is left alone, any
+    /// other focus ends the switch.
+    pub fn focused(&mut self, id: i64) {
+        self.expire();
+        if self.switch.as_ref().is_some_and(|s| s.window == id) {
+            return;
+        }
+        self.end_switch();
+        self.push(id);
+    }
+
+    /// Ends a switch, which brings the window it ended on to the front.
+    pub fn end_switch(&mut self) {
+        if let Some(switch) = self.switch.take() {
+            self.push(switch.window);
+        }
+    }
+
+    /// Ends a switch that waited too long for its next step. There is no need for
+    /// a timer, nothing looks at the history in the meantime.
+    fn expire(&mut self) {
+        if self
+            .switch
+            .as_ref()
+            .is_some_and(|s| s.last_step.elapsed() >= self.switch_timeout)
+        {
+            self.end_switch();
+        }
+    }
+
+    fn push(&mut self, id: i64) {
+        self.windows.retain(|w| *w != id);
+        self.windows.push_front(id);
+        self.windows.truncate(self.size);
+    }
+
+    pub fn remove(&mut self, id: i64) {
+        self.windows.retain(|w| *w != id);
+        if self.switch.as_ref().is_some_and(|s| s.window == id) {
+            self.switch = None;
+        }
+    }
+
+    /// Forgets windows which are gone.
+    pub fn retain<F: Fn(i64) -> bool>(&mut self, exists: F) {
+        self.windows.retain(|w| exists(*w));
+        if self.switch.as_ref().is_some_and(|s| !exists(s.window)) {
+            self.switch = None;
+        }
+    }
+
+    pub fn windows(&mut self) -> impl Iterator<Item = i64> + '_ {
+        self.expire();
+        self.windows.iter().copied()
+    }
+
+    /// The window one step further in a switch through `windows`, the part of
+    /// the history to switch between in the order of the history. Going
+    /// backwards starts from the window that had focus the longest ago.
+    pub fn switch(&mut self, windows: &[i64], forward: bool) -> Option<i64> {
+        self.expire();
+        if windows.len() < 2 {
+            return None;
+        }
+        let position = match &self.switch {
+            Some(switch) if forward => (switch.position + 1) % windows.len(),
+            Some(switch) => (switch.position + windows.len() - 1) % windows.len(),
+            None if forward => 1,
+            None => windows.len() - 1,
+        };
+        let window = windows[position];
+        self.switch = Some(Switch {
+            position,
+            window,
+            last_step: Instant::now(),
+        });
+        Some(window)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A history of windows 1, 2 and 3 focused in that order.
+    fn history(switch_timeout: Duration) -> History {
+        let mut history = History::new(DEFAULT_SIZE, switch_timeout);
+        for id in [1, 2, 3] {
+            history.focused(id);
+        }
+        history
+    }
+
+    fn windows(history: &mut History) -> Vec<i64> {
+        history.windows().collect()
+    }
+
+    /// Takes a step the way the daemon does, focusing the window it lands on.
+    fn step(history: &mut History, forward: bool) -> Option<i64> {
+        let order = windows(history);
+        let window = history.switch(&order, forward)?;
+        history.focused(window);
+        Some(window)
+    }
+
+    #[test]
+    fn next_wraps_around_with_the_history_frozen() {
+        let mut history = history(DEFAULT_SWITCH_TIMEOUT);
+        assert_eq!(windows(&mut history), vec![3, 2, 1]);
+        let steps: Vec<_> = (0..4).map(|_| step(&mut history, true)).collect();
+        assert_eq!(steps, vec![Some(2), Some(1), Some(3), Some(2)]);
+        assert_eq!(windows(&mut history), vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn prev_starts_from_the_oldest_and_wraps_around() {
+        let mut history = history(DEFAULT_SWITCH_TIMEOUT);
+        let steps: Vec<_> = (0..4).map(|_| step(&mut history, false)).collect();
+        assert_eq!(steps, vec![Some(1), Some(2), Some(3), Some(1)]);
+    }
+
+    #[test]
+    fn end_brings_the_window_to_the_front() {
+        let mut history = history(DEFAULT_SWITCH_TIMEOUT);
+        step(&mut history, true);
+        step(&mut history, true);
+        history.end_switch();
+        assert_eq!(windows(&mut history), vec![1, 3, 2]);
+        // the next switch starts over from the front
+        assert_eq!(step(&mut history, true), Some(3));
+    }
+
+    #[test]
+    fn a_switch_ends_after_the_timeout() {
+        let mut history = history(Duration::ZERO);
+        step(&mut history, true);
+        assert_eq!(windows(&mut history), vec![2, 3, 1]);
+        assert_eq!(step(&mut history, true), Some(3));
+    }
+
+    #[test]
+    fn focus_from_elsewhere_ends_the_switch() {
+        let mut history = history(DEFAULT_SWITCH_TIMEOUT);
+        step(&mut history, true);
+        history.focused(1);
+        assert_eq!(windows(&mut history), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn a_closed_window_ends_the_switch_on_it() {
+        let mut history = history(DEFAULT_SWITCH_TIMEOUT);
+        step(&mut history, true);
+        history.remove(2);
+        history.end_switch();
+        assert_eq!(windows(&mut history), vec![3, 1]);
+    }
+
+    #[test]
+    fn nothing_to_switch_to_with_one_window() {
+        let mut history = History::new(DEFAULT_SIZE, DEFAULT_SWITCH_TIMEOUT);
+        history.focused(1);
+        assert_eq!(step(&mut history, true), None);
+        assert_eq!(step(&mut history, false), None);
+    }
+}
@@ -0,0 +1,723 @@
+use crate::connection;
+use crate::criteria::{Criteria, Subject};
+use crate::exec::Exec;
+use crate::template::{Segment, Template};
+use crate::tree;
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::fmt;
+use swayipc_async::{Connection, Error, Fallible, Node, WindowChange, WorkspaceChange};
+
+/// Every hook by its name in the config file.
+pub const NAMES: &[&str] = &[
+    "on-window-focus",
+    "on-window-focus-leave",
+    "on-window-new",
+    "on-window-close",
+    "on-window-move",
+    "on-window-floating",
+    "on-window-fullscreen-mode",
+    "on-window-title",
+    "on-window-urgent",
+    "on-window-mark",
+    "on-workspace-focus",
+    "on-workspace-init",
+    "on-workspace-empty",
+    "on-workspace-move",
+    "on-workspace-rename",
+    "on-workspace-urgent",
+    "on-output-added",
+    "on-output-removed",
+    "on-mode-change",
+    "on-bar-config-update",
+    "on-binding",
+    "on-tick",
+    "on-exit",
+];
+
+/// What sway command hooks can have filled in, see `Context`.
+const PLACEHOLDERS: &[&str] = &[
+    "hook",
+    "change",
+    "con_id",
+    "prev_con_id",
+    "app_id",
+    "class",
+    "title",
+    "pid",
+    "workspace",
+    "output",
+    "mode",
+    "payload",
+];
+
+/// Sway commands with placeholders for what the hook runs for, eg.
+/// `[con_id={prev_con_id}] mark --add _prev`. Use `{{` and `}}` for literal braces.
+#[derive(Debug, Clone)]
+pub struct Commands {
+    source: String,
+    template: Template,
+}
+
+impl Commands {
+    pub fn parse(source: String) -> Result<Self> {
+        let template = Template::parse(&source, PLACEHOLDERS)?;
+        Ok(Commands { source, template })
+    }
+
+    /// Whether a placeholder is used which the context has no value for.
+    pub fn missing(&self, context: &Context) -> bool {
+        self.template
+            .segments()
+            .iter()
+            .any(|segment| match segment {
+                Segment::Placeholder(name) => context.value(name).is_none(),
+                Segment::Literal(_) => false,
+            })
+    }
+
+    /// Fills in the placeholders, escaping the values so they stay a single
+    /// argument: within quotes only the quotes are escaped, elsewhere values
+    /// that are more than a plain word are put in double quotes. Sway hands
+    /// `exec` commands to a shell as they are, so there values are put in
+    /// single quotes for the shell instead. Commands with a placeholder the
+    /// context has no value for are left out.
+    pub fn render(&self, context: &Context) -> String {
+        let mut rendered = vec![];
+        let mut current = String::new();
+        // where the command the next placeholder belongs to starts in `current`
+        let mut start = 0;
+        let mut missing = false;
+        let mut quoting = Quoting::default();
+        for segment in self.template.segments() {
+            match segment {
+                Segment::Literal(literal) => {
+                    for c in literal.chars() {
+                        match c {
+                            ';' if quoting.bare(c) => {
+                                if !missing {
+                                    rendered.push(std::mem::take(&mut current));
+                                }
+                                current.clear();
+                                start = 0;
+                                missing = false;
+                                continue;
+                            }
+                            ',' if quoting.bare(c) => start = current.len() + 1,
+                            // the guards above have taken the character already
+                            ';' | ',' => {}
+                            _ => {
+                                quoting.bare(c);
+                            }
+                        }
+                        current.push(c);
+                    }
+                }
+                Segment::Placeholder(name) => match context.value(name) {
+                    Some(value) if is_exec(&current[start..]) => {
+                        current.push_str(&shell_quote(&value, quoting.quote))
+                    }
+                    Some(value) => current.push_str(&sway_quote(&value, quoting.quote)),
+                    None => missing = true,
+                },
+            }
+        }
+        if !missing {
+            rendered.push(current);
+        }
+        rendered
+            .iter()
+            .map(|cmd| cmd.trim())
+            .filter(|cmd| !cmd.is_empty())
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+impl fmt::Display for Commands {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+/// Whether a single command, criteria and all, is run by a shell.
+fn is_exec(cmd: &str) -> bool {
+    let (_, cmd) = split_criteria(cmd.trim());
+    matches!(cmd.split_whitespace().next(), Some("exec" | "exec_always"))
+}
+
+/// A value for sway, within the given quotes or none.
+fn sway_quote(value: &str, quote: Option<char>) -> String {
+    let is_word = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_alphanumeric() || "_-.:/".contains(c));
+    match quote {
+        Some(q) => escape(value, q),
+        None if is_word => value.to_string(),
+        None => format!("\"{}\"", escape(value, '"')),
+    }
+}
+
+fn escape(value: &str, quote: char) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(quote, &format!("\\{}", quote))
+}
+
+/// A value for the shell sway runs `exec` commands with, within the given
+/// quotes or none. The value goes in single quotes, where the shell takes
+/// everything as it is, with `'` and `\` written outside of them so that sway
+/// still sees the quotes balanced. Within double quotes those are closed
+/// around the value.
+fn shell_quote(value: &str, quote: Option<char>) -> String {
+    let inner: String = value
+        .chars()
+        .map(|c| match c {
+            '\'' => r"'\''".to_string(),
+            '\\' => r"'\\'".to_string(),
+            c => c.to_string(),
+        })
+        .collect();
+    match quote {
+        Some('\'') => inner,
+        Some(_) => format!("\"'{}'\"", inner),
+        None => format!("'{}'", inner),
+    }
+}
+
+/// Keeps track of quotes and escapes in sway commands the way sway's argsep
+/// does: a `\` escapes the next character, within quotes or not, and a quote
+/// of one kind is an ordinary character within quotes of the other.
+#[derive(Debug, Default)]
+struct Quoting {
+    quote: Option<char>,
+    escaped: bool,
+}
+
+impl Quoting {
+    /// Takes the next character, returning whether it is outside of quotes
+    /// and not escaped, that is whether it can separate commands.
+    fn bare(&mut self, c: char) -> bool {
+        if self.escaped {
+            self.escaped = false;
+            return false;
+        }
+        match (c, self.quote) {
+            ('\\', _) => self.escaped = true,
+            ('"' | '\'', None) => self.quote = Some(c),
+            (c, Some(q)) if c == q => self.quote = None,
+            (_, Some(_)) => {}
+            _ => return true,
+        }
+        false
+    }
+}
+
+/// What a hook does when it runs.
+#[derive(Debug, Clone)]
+pub enum Action {
+    /// Sway commands.
+    Sway(Commands),
+    Exec(Exec),
+    /// The first rule that matches decides.
+    Rules(Vec<Rule>),
+}
+
+/// Runs its action only for windows, workspaces and outputs which match.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub criteria: Criteria,
+    pub action: Action,
+}
+
+impl Action {
+    /// What to do for the subject, which is nothing when no rule matches it.
+    pub fn select(&self, subject: &Subject) -> Option<&Action> {
+        match self {
+            Action::Rules(rules) => rules
+                .iter()
+                .find(|rule| rule.criteria.matches(subject))?
+                .action
+                .select(subject),
+            action => Some(action),
+        }
+    }
+
+    /// All sway commands this might run.
+    pub fn commands(&self) -> Vec<&Commands> {
+        match self {
+            Action::Sway(commands) => vec![commands],
+            Action::Exec(_) => vec![],
+            Action::Rules(rules) => rules.iter().flat_map(|r| r.action.commands()).collect(),
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Sway(cmds) => write!(f, "{}", cmds),
+            Action::Exec(exec) => write!(f, "{}", exec),
+            Action::Rules(rules) => write!(f, "{} rules", rules.len()),
+        }
+    }
+}
+
+/// What a hook runs for, as far as it is known, eg. the window that got focus.
+#[derive(Debug, Default, Serialize)]
+pub struct Context {
+    pub hook: &'static str,
+    /// The change sway reported, eg. focus or new.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub con_id: Option<i64>,
+    /// The window that had focus before, for focus hooks.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prev_con_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub class: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub floating: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+    /// The command of a binding, or the payload of a tick.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload: Option<String>,
+}
+
+impl Context {
+    pub fn new(hook: &'static str) -> Self {
+        Context {
+            hook,
+            ..Context::default()
+        }
+    }
+
+    /// The context of a hook for a window.
+    pub fn window(hook: &'static str, node: &Node) -> Self {
+        let mut context = Context::new(hook);
+        context.set_window(node);
+        context
+    }
+
+    pub fn set_window(&mut self, node: &Node) {
+        self.con_id = Some(node.id);
+        self.app_id = node.app_id.clone();
+        self.class = node
+            .window_properties
+            .as_ref()
+            .and_then(|p| p.class.clone());
+        self.title = node.name.clone();
+        self.pid = node.pid;
+        self.floating = Some(tree::is_floating(node));
+    }
+
+    /// Sets the change from the sway event, named as sway does, eg. fullscreen_mode.
+    pub fn change(mut self, change: impl fmt::Debug) -> Self {
+        let mut name = String::new();
+        for (i, c) in format!("{:?}", change).chars().enumerate() {
+            if c.is_uppercase() && i > 0 {
+                name.push('_');
+            }
+            name.push(c.to_ascii_lowercase());
+        }
+        self.change = Some(name);
+        self
+    }
+
+    /// A made up context for checking hooks, with a value for every placeholder.
+    pub fn sample() -> Self {
+        let word = || Some("persway".to_string());
+        Context {
+            hook: "sample",
+            change: word(),
+            con_id: Some(0),
+            prev_con_id: Some(0),
+            app_id: word(),
+            class: word(),
+            title: word(),
+            pid: Some(0),
+            floating: Some(false),
+            workspace: word(),
+            output: word(),
+            mode: word(),
+            payload: word(),
+        }
+    }
+
+    pub fn subject(&self) -> Subject<'_> {
+        Subject {
+            app_id: self.app_id.as_deref(),
+            class: self.class.as_deref(),
+            title: self.title.as_deref(),
+            floating: self.floating,
+            workspace: self.workspace.as_deref(),
+            output: self.output.as_deref(),
+        }
+    }
+
+    /// Every field which has a value, by name.
+    fn fields(&self) -> Vec<(String, String)> {
+        let json = match serde_json::to_value(self) {
+            Ok(serde_json::Value::Object(fields)) => fields,
+            _ => return vec![],
+        };
+        json.into_iter()
+            .map(|(key, value)| match value {
+                serde_json::Value::String(s) => (key, s),
+                other => (key, other.to_string()),
+            })
+            .collect()
+    }
+
+    fn value(&self, name: &str) -> Option<String> {
+        self.fields()
+            .into_iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+    }
+
+    /// The context as `PERSWAY_*` environment variables, eg. `PERSWAY_CON_ID`.
+    pub fn env(&self) -> Vec<(String, String)> {
+        self.fields()
+            .into_iter()
+            .map(|(key, value)| (format!("PERSWAY_{}", key.to_uppercase()), value))
+            .collect()
+    }
+}
+
+/// The hook for a change to a window other than focus, which has hooks of its own.
+pub fn window_hook(change: &WindowChange) -> Option<&'static str> {
+    match change {
+        WindowChange::New => Some("on-window-new"),
+        WindowChange::Close => Some("on-window-close"),
+        WindowChange::Move => Some("on-window-move"),
+        WindowChange::Floating => Some("on-window-floating"),
+        WindowChange::FullscreenMode => Some("on-window-fullscreen-mode"),
+        WindowChange::Title => Some("on-window-title"),
+        WindowChange::Urgent => Some("on-window-urgent"),
+        WindowChange::Mark => Some("on-window-mark"),
+        _ => None,
+    }
+}
+
+/// The hook for a change to a workspace.
+pub fn workspace_hook(change: &WorkspaceChange) -> Option<&'static str> {
+    match change {
+        WorkspaceChange::Focus => Some("on-workspace-focus"),
+        WorkspaceChange::Init => Some("on-workspace-init"),
+        WorkspaceChange::Empty => Some("on-workspace-empty"),
+        WorkspaceChange::Move => Some("on-workspace-move"),
+        WorkspaceChange::Rename => Some("on-workspace-rename"),
+        WorkspaceChange::Urgent => Some("on-workspace-urgent"),
+        _ => None,
+    }
+}
+
+/// What sway answers when criteria match no window, which for hooks is
+/// business as usual rather than a failure.
+const NO_MATCH: &str = "No matching node";
+
+/// Splits sway commands into the single commands sway reports an outcome for,
+/// that is on `;` and `,` outside of quotes and criteria.
+fn split_commands(cmds: &str) -> Vec<&str> {
+    split(cmds, &[';', ','])
+}
+
+fn split<'a>(cmds: &'a str, separators: &[char]) -> Vec<&'a str> {
+    let mut parts = vec![];
+    let mut quoting = Quoting::default();
+    let mut in_criteria = false;
+    let mut start = 0;
+    for (i, c) in cmds.char_indices() {
+        if !quoting.bare(c) {
+            continue;
+        }
+        match c {
+            '[' => in_criteria = true,
+            ']' => in_criteria = false,
+            c if !in_criteria && separators.contains(&c) => {
+                parts.push(cmds[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(cmds[start..].trim());
+    parts.retain(|p| !p.is_empty());
+    parts
+}
+
+/// Scopes a hook to a window by putting `[con_id=…]` in front of every `;`
+/// separated command that has no criteria of its own.
+pub fn scoped(cmds: &str, con_id: i64) -> String {
+    split(cmds, &[';'])
+        .into_iter()
+        .map(|cmd| match split_criteria(cmd) {
+            (Some(_), _) => cmd.to_string(),
+            (None, _) => format!("[con_id={}] {}", con_id, cmd),
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Splits a single command into its criteria, if any, and the command itself.
+fn split_criteria(cmd: &str) -> (Option<&str>, &str) {
+    if !cmd.starts_with('[') {
+        return (None, cmd);
+    }
+    let mut quoting = Quoting::default();
+    for (i, c) in cmd.char_indices() {
+        if quoting.bare(c) && c == ']' {
+            return (Some(&cmd[..=i]), cmd[i + 1..].trim());
+        }
+    }
+    (None, cmd)
+}
+
+/// The single commands that failed along with why, leaving out criteria that
+/// matched nothing.
+fn failures<'a>(cmds: &'a str, outcomes: &[Fallible<()>]) -> Vec<(&'a str, String)> {
+    let parts = split_commands(cmds);
+    outcomes
+        .iter()
+        .enumerate()
+        .filter_map(|(i, outcome)| match outcome {
+            Err(Error::CommandFailed(e)) if e.starts_with(NO_MATCH) => None,
+            Err(e) => Some((parts.get(i).copied().unwrap_or(cmds), e.to_string())),
+            Ok(()) => None,
+        })
+        .collect()
+}
+
+/// Runs sway commands, reporting every part of them that sway did not accept
+/// under the given label, eg. the name of a hook.
+pub async fn run(conn: &mut Connection, label: &str, cmds: &str) -> Result<()> {
+    if cmds.is_empty() {
+        return Ok(());
+    }
+    let outcomes = connection::run_command(conn, cmds).await?;
+    for (part, e) in failures(cmds, &outcomes) {
+        log::warn!("{}: '{}' failed: {}", label, part, e);
+    }
+    Ok(())
+}
+
+/// Checks a hook without running it. Every command is sent once with criteria
+/// that match no window, so sway rejects unknown commands without anything
+/// happening, and its criteria are tried with `nop`. Sway stops at the criteria
+/// matching nothing before it looks at the arguments of a command, so those
+/// are not checked, eg. `opacity banana` passes.
+pub async fn validate(conn: &mut Connection, name: &str, cmds: &str) -> Result<()> {
+    let mut checks = vec![];
+    for part in split_commands(cmds) {
+        let (criteria, cmd) = split_criteria(part);
+        if let Some(criteria) = criteria {
+            checks.push((part, format!("{} nop", criteria)));
+        }
+        if !cmd.is_empty() {
+            checks.push((part, format!("[con_id=-1] {}", cmd)));
+        }
+    }
+    let dry_run: Vec<&str> = checks.iter().map(|(_, check)| check.as_str()).collect();
+    let outcomes = connection::run_command(conn, dry_run.join("; ")).await?;
+    let errors: Vec<String> = checks
+        .iter()
+        .zip(&outcomes)
+        .filter_map(|((part, _), outcome)| match outcome {
+            Err(Error::CommandFailed(e)) if e.starts_with(NO_MATCH) => None,
+            Err(e) => Some(format!("'{}': {}", part, e)),
+            Ok(()) => None,
+        })
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("{} hook is invalid, {}", name, errors.join(", ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(cmds: &str, context: &Context) -> String {
+        Commands::parse(cmds.to_string()).unwrap().render(context)
+    }
+
+    fn titled(title: &str) -> Context {
+        Context {
+            con_id: Some(42),
+            title: Some(title.to_string()),
+            ..Context::new("on-window-focus")
+        }
+    }
+
+    #[test]
+    fn render_plain_words_as_they_are() {
+        let cmds = "[con_id={con_id}] mark {title}";
+        assert_eq!(render(cmds, &titled("web")), "[con_id=42] mark web");
+    }
+
+    #[test]
+    fn render_quotes_values_outside_of_quotes() {
+        let cmds = "title_format {title}";
+        assert_eq!(
+            render(cmds, &titled(r#"say "hi"; exit"#)),
+            r#"title_format "say \"hi\"; exit""#
+        );
+        assert_eq!(render(cmds, &titled("")), r#"title_format """#);
+    }
+
+    #[test]
+    fn render_escapes_within_quotes() {
+        assert_eq!(
+            render("title_format '{title}'; border pixel 2", &titled("It's")),
+            r"title_format 'It\'s'; border pixel 2"
+        );
+        assert_eq!(
+            render(r#"title_format "{title}""#, &titled(r#"a\"b"#)),
+            r#"title_format "a\\\"b""#
+        );
+    }
+
+    #[test]
+    fn render_quotes_for_the_shell_in_exec() {
+        let context = titled("$(touch /tmp/pwn)");
+        assert_eq!(
+            render("exec notify-send {title}", &context),
+            "exec notify-send '$(touch /tmp/pwn)'"
+        );
+        assert_eq!(
+            render(r#"exec notify-send "got {title}""#, &context),
+            r#"exec notify-send "got "'$(touch /tmp/pwn)'"""#
+        );
+        assert_eq!(
+            render(
+                "exec notify-send '{title}'; border pixel 2",
+                &titled("It's")
+            ),
+            r"exec notify-send 'It'\''s'; border pixel 2"
+        );
+        assert_eq!(
+            render("[app_id=foot] exec_always echo {title}", &titled(r"a\")),
+            r"[app_id=foot] exec_always echo 'a'\\''"
+        );
+        // only the exec part of a chain goes to the shell
+        assert_eq!(
+            render("mark {title}, exec echo {title}", &titled("a b")),
+            r#"mark "a b", exec echo 'a b'"#
+        );
+    }
+
+    #[test]
+    fn render_keeps_shell_quoted_values_balanced_for_sway() {
+        let rendered = render(
+            "exec notify-send '{title}'; border pixel 2",
+            &titled(r"It's a \ test"),
+        );
+        assert_eq!(
+            split_commands(&rendered),
+            vec![r"exec notify-send 'It'\''s a '\\' test'", "border pixel 2"]
+        );
+    }
+
+    #[test]
+    fn render_leaves_out_commands_with_missing_values() {
+        let context = titled("web");
+        assert_eq!(
+            render(
+                "[con_id={prev_con_id}] mark --add _prev; opacity 1",
+                &context
+            ),
+            "opacity 1"
+        );
+        assert_eq!(
+            render(
+                "opacity 1; [con_id={prev_con_id}] mark _prev, focus",
+                &context
+            ),
+            "opacity 1"
+        );
+        assert_eq!(render("mark {app_id}", &context), "");
+    }
+
+    #[test]
+    fn split_commands_on_separators_outside_of_quotes_and_criteria() {
+        assert_eq!(
+            split_commands(r#"[title="a;b"] focus, mark 'x,y'; exec echo "1;2""#),
+            vec![r#"[title="a;b"] focus"#, "mark 'x,y'", r#"exec echo "1;2""#]
+        );
+        assert_eq!(split_commands(" a ;; b ,"), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn split_commands_minds_escapes() {
+        assert_eq!(
+            split_commands(r"mark 'It\'s; fine'; border pixel 2"),
+            vec![r"mark 'It\'s; fine'", "border pixel 2"]
+        );
+        assert_eq!(
+            split_commands(r#"mark "a\\"; border pixel 2"#),
+            vec![r#"mark "a\\""#, "border pixel 2"]
+        );
+        assert_eq!(
+            split_commands(r"exec echo a\;b; border pixel 2"),
+            vec![r"exec echo a\;b", "border pixel 2"]
+        );
+    }
+
+    #[test]
+    fn split_criteria_from_commands() {
+        assert_eq!(
+            split_criteria(r#"[title="]"] focus"#),
+            (Some(r#"[title="]"]"#), "focus")
+        );
+        assert_eq!(
+            split_criteria(r#"[title="\"]"] focus"#),
+            (Some(r#"[title="\"]"]"#), "focus")
+        );
+        assert_eq!(split_criteria("focus"), (None, "focus"));
+        assert_eq!(split_criteria("[unclosed focus"), (None, "[unclosed focus"));
+    }
+
+    #[test]
+    fn scoped_adds_criteria_where_there_are_none() {
+        assert_eq!(
+            scoped("opacity 1; [app_id=foot] opacity 0.5, border none", 42),
+            "[con_id=42] opacity 1; [app_id=foot] opacity 0.5, border none"
+        );
+        assert_eq!(
+            scoped(r"exec notify-send 'It\'s'; border pixel 2", 42),
+            r"[con_id=42] exec notify-send 'It\'s'; [con_id=42] border pixel 2"
+        );
+    }
+
+    #[test]
+    fn failures_name_the_command_that_failed() {
+        let cmds = "[app_id=foot] focus, nonsense; mark 'a;b'; bogus";
+        let outcomes = vec![
+            Err(Error::CommandFailed(format!("{} for criteria", NO_MATCH))),
+            Err(Error::CommandFailed("Unknown/invalid command".to_string())),
+            Ok(()),
+            Err(Error::CommandFailed("Unknown/invalid command".to_string())),
+        ];
+        let failed: Vec<&str> = failures(cmds, &outcomes)
+            .into_iter()
+            .map(|(part, _)| part)
+            .collect();
+        assert_eq!(failed, vec!["nonsense", "bogus"]);
+    }
+}
@@ -0,0 +1,202 @@
+use crate::connection;
+use crate::tree;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::fmt;
+use swayipc_async::{Connection, Node, NodeLayout, NodeType, WindowChange, WindowEvent};
+
+/// How the alternating layout splits windows, the `[alternating]` table in the
+/// config file, eg. for a portrait monitor:
+///
+/// [alternating]
+/// split-ratio = 0.5
+/// min-height = 300
+/// small-layout = "stacked"
+/// max-depth = 4
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Alternating {
+    /// Windows at least this many times as wide as they are tall are split
+    /// side by side, others one above the other.
+    pub split_ratio: f64,
+    /// Windows are not split into windows narrower than this many pixels.
+    pub min_width: i32,
+    /// Windows are not split into windows shorter than this many pixels.
+    pub min_height: i32,
+    /// What windows too small to split either way get instead.
+    pub small_layout: SmallLayout,
+    /// Windows this many split containers deep are not split any further.
+    pub max_depth: Option<usize>,
+}
+
+impl Default for Alternating {
+    fn default() -> Self {
+        Alternating {
+            split_ratio: 1.0,
+            min_width: 0,
+            min_height: 0,
+            small_layout: SmallLayout::Tabbed,
+            max_depth: None,
+        }
+    }
+}
+
+impl Alternating {
+    pub fn check(&self) -> Result<()> {
+        if self.split_ratio <= 0.0 {
+            return Err(anyhow!(
+                "alternating: split-ratio must be above 0, got {}",
+                self.split_ratio
+            ));
+        }
+        Ok(())
+    }
+
+    /// The command for the focused window of the given size, `depth` split
+    /// containers deep.
+    fn command(&self, width: i32, height: i32, depth: usize) -> Option<&'static str> {
+        if self.max_depth.is_some_and(|max| depth >= max) {
+            return None;
+        }
+        let fits_h = width / 2 >= self.min_width;
+        let fits_v = height / 2 >= self.min_height;
+        let wide = f64::from(width) >= f64::from(height) * self.split_ratio;
+        match (wide, fits_h, fits_v) {
+            (true, true, _) | (false, true, false) => Some("split h"),
+            (false, _, true) | (true, false, true) => Some("split v"),
+            (_, false, false) => Some(self.small_layout.command()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SmallLayout {
+    Tabbed,
+    Stacked,
+}
+
+impl SmallLayout {
+    fn command(self) -> &'static str {
+        // split first, so the window and the ones opened next to it get a
+        // container of their own rather than changing their parent
+        match self {
+            SmallLayout::Tabbed => "split v; layout tabbed",
+            SmallLayout::Stacked => "split v; layout stacking",
+        }
+    }
+}
+
+impl fmt::Display for SmallLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SmallLayout::Tabbed => f.write_str("tabbed"),
+            SmallLayout::Stacked => f.write_str("stacked"),
+        }
+    }
+}
+
+/// The number of split containers between a window and its workspace.
+fn depth(tree: &Node, id: i64) -> usize {
+    tree::find_with_ancestors(tree, id)
+        .map(|(_, ancestors)| {
+            ancestors
+                .iter()
+                .take_while(|n| n.node_type != NodeType::Workspace)
+                .count()
+        })
+        .unwrap_or_default()
+}
+
+pub async fn handle(
+    event: &WindowEvent,
+    tree: &Node,
+    options: &Alternating,
+    conn: &mut Connection,
+) -> Result<()> {
+    if event.change != WindowChange::Focus {
+        return Ok(());
+    }
+    let focused = tree
+        .find_focused_as_ref(|n| n.focused)
+        .ok_or_else(|| anyhow!("No focused node"))?;
+    let parent = tree
+        .find_focused_as_ref(|n| n.nodes.iter().any(|n| n.focused))
+        .ok_or_else(|| anyhow!("No parent"))?;
+    let is_floating = tree::is_floating(focused);
+    let is_full_screen = tree::is_full_screen(focused);
+    let is_stacked = parent.layout == NodeLayout::Stacked;
+    let is_tabbed = parent.layout == NodeLayout::Tabbed;
+    if !is_floating && !is_full_screen && !is_stacked && !is_tabbed {
+        let rect = &focused.rect;
+        if let Some(cmd) = options.command(rect.width, rect.height, depth(tree, focused.id)) {
+            connection::run_command(conn, cmd).await?;
+        }
+    };
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sized(min_width: i32, min_height: i32) -> Alternating {
+        Alternating {
+            min_width,
+            min_height,
+            ..Alternating::default()
+        }
+    }
+
+    #[test]
+    fn wide_windows_split_side_by_side() {
+        let options = Alternating::default();
+        assert_eq!(options.command(800, 600, 0), Some("split h"));
+        assert_eq!(options.command(600, 600, 0), Some("split h"));
+        assert_eq!(options.command(600, 800, 0), Some("split v"));
+    }
+
+    #[test]
+    fn split_ratio_sets_what_counts_as_wide() {
+        let options = Alternating {
+            split_ratio: 2.0,
+            ..Alternating::default()
+        };
+        assert_eq!(options.command(800, 600, 0), Some("split v"));
+        assert_eq!(options.command(1200, 600, 0), Some("split h"));
+    }
+
+    #[test]
+    fn windows_split_the_way_that_fits() {
+        // wide, but too narrow to split side by side
+        assert_eq!(sized(500, 0).command(800, 600, 0), Some("split v"));
+        // tall, but too short to split one above the other
+        assert_eq!(sized(0, 400).command(600, 700, 0), Some("split h"));
+    }
+
+    #[test]
+    fn windows_too_small_either_way_get_the_small_layout() {
+        let options = sized(500, 400);
+        assert_eq!(options.command(800, 600, 0), Some("split v; layout tabbed"));
+        let options = Alternating {
+            small_layout: SmallLayout::Stacked,
+            ..options
+        };
+        assert_eq!(
+            options.command(800, 600, 0),
+            Some("split v; layout stacking")
+        );
+    }
+
+    #[test]
+    fn max_depth_stops_splitting() {
+        let options = Alternating {
+            max_depth: Some(2),
+            ..sized(500, 400)
+        };
+        assert_eq!(options.command(800, 1200, 1), Some("split v"));
+        assert_eq!(options.command(800, 1200, 2), None);
+        assert_eq!(options.command(800, 600, 3), None);
+    }
+}
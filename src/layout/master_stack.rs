@@ -0,0 +1,261 @@
+use super::{side_by_side, stack_onto};
+use crate::connection;
+use crate::tree;
+use anyhow::{anyhow, Result};
+use std::str::FromStr;
+use swayipc_async::{Connection, Node};
+
+/// How much grow and shrink change the width of the master by default, in percent.
+const DEFAULT_RESIZE_STEP: i32 = 5;
+
+/// Rearranges a workspace laid out as a master and a stack, over the control socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MasterCommand {
+    /// Makes the focused window the master, the old master goes to the top of
+    /// the stack. When the master has focus the top of the stack takes its place.
+    Promote,
+    /// Swaps the focused window and the master, or the master and the top of
+    /// the stack when the master has focus.
+    Swap,
+    /// Moves every window one place on, from the master down the stack and
+    /// from the bottom of the stack back to the master, or the other way around.
+    Rotate { clockwise: bool },
+    /// Makes the master this many percent wider, or narrower when negative.
+    Resize(i32),
+}
+
+impl FromStr for MasterCommand {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, arg) = s.split_once(' ').unwrap_or((s, ""));
+        let arg = arg.trim();
+        let step = || -> Result<i32> {
+            if arg.is_empty() {
+                return Ok(DEFAULT_RESIZE_STEP);
+            }
+            arg.parse()
+                .map_err(|_| anyhow!("expected a percentage, got '{}'", arg))
+        };
+        match (name, arg) {
+            ("promote", "") => Ok(MasterCommand::Promote),
+            ("swap", "") => Ok(MasterCommand::Swap),
+            ("rotate", "cw") => Ok(MasterCommand::Rotate { clockwise: true }),
+            ("rotate", "ccw") => Ok(MasterCommand::Rotate { clockwise: false }),
+            ("rotate", _) => Err(anyhow!("expected cw or ccw")),
+            ("grow", _) => Ok(MasterCommand::Resize(step()?)),
+            ("shrink", _) => Ok(MasterCommand::Resize(-step()?)),
+            _ => Err(anyhow!(
+                "expected promote, swap, rotate <cw|ccw>, grow [ppt] or shrink [ppt]"
+            )),
+        }
+    }
+}
+
+/// Keeps the tiled windows of a workspace as a master on the left and a
+/// vertical stack on the right. New windows opened next to the master go to
+/// the bottom of the stack and when the master goes away the top of the stack
+/// takes its place.
+pub async fn arrange(ws: &Node, conn: &mut Connection) -> Result<()> {
+    let mut cmds: Vec<String> = side_by_side(ws).into_iter().collect();
+    cmds.extend(match ws.nodes.as_slice() {
+        [] => vec![],
+        [only] => match only.nodes.first() {
+            Some(first) if only.nodes.len() > 1 => vec![format!("[con_id={}] move left", first.id)],
+            _ => vec![],
+        },
+        [_master, stack] if tree::is_window(stack) => {
+            vec![format!("[con_id={}] split v", stack.id)]
+        }
+        [_master, _stack] => vec![],
+        [_master, rest @ ..] => {
+            let stack = rest
+                .iter()
+                .find(|n| !tree::is_window(n))
+                .unwrap_or(&rest[0]);
+            let others: Vec<&Node> = rest.iter().filter(|n| n.id != stack.id).collect();
+            stack_onto(stack, &others)
+        }
+    });
+    if !cmds.is_empty() {
+        connection::run_command(conn, cmds.join("; ")).await?;
+    }
+    Ok(())
+}
+
+/// The master and the entries of the stack, top first, of a workspace laid
+/// out as a master and a stack.
+fn parts(ws: &Node) -> Option<(&Node, Vec<&Node>)> {
+    match ws.nodes.as_slice() {
+        [master, stack] if tree::is_window(master) && tree::is_window(stack) => {
+            Some((master, vec![stack]))
+        }
+        [master, stack] if tree::is_window(master) => Some((master, stack.nodes.iter().collect())),
+        _ => None,
+    }
+}
+
+/// Commands swapping windows around until they are in the order of `target`.
+fn reorder(current: &[i64], target: &[i64]) -> Vec<String> {
+    let mut current = current.to_vec();
+    let mut cmds = vec![];
+    for (i, id) in target.iter().enumerate() {
+        if current[i] == *id {
+            continue;
+        }
+        if let Some(j) = current.iter().position(|c| c == id) {
+            cmds.push(format!(
+                "[con_id={}] swap container with con_id {}",
+                current[i], id
+            ));
+            current.swap(i, j);
+        }
+    }
+    cmds
+}
+
+/// The order of the windows, master first, after a command that moves them
+/// around, `focused` being the position of the focused window in `current`.
+fn target(command: MasterCommand, current: &[i64], focused: Option<usize>) -> Result<Vec<i64>> {
+    let mut target = current.to_vec();
+    if current.len() < 2 {
+        return Ok(target);
+    }
+    match command {
+        MasterCommand::Resize(_) => {}
+        MasterCommand::Rotate { clockwise: true } => target.rotate_right(1),
+        MasterCommand::Rotate { clockwise: false } => target.rotate_left(1),
+        MasterCommand::Promote | MasterCommand::Swap => {
+            let focused = focused.ok_or_else(|| anyhow!("the focused window is not tiled here"))?;
+            let promoted = if focused == 0 { 1 } else { focused };
+            if command == MasterCommand::Promote {
+                let window = target.remove(promoted);
+                target.insert(0, window);
+            } else {
+                target.swap(0, promoted);
+            }
+        }
+    }
+    Ok(target)
+}
+
+/// Runs a master command on the workspace `ws`, which is part of `tree`.
+pub async fn run(
+    command: MasterCommand,
+    tree: &Node,
+    ws: &Node,
+    conn: &mut Connection,
+) -> Result<()> {
+    let (master, stack) = parts(ws)
+        .ok_or_else(|| anyhow!("the workspace is not laid out as a master and a stack"))?;
+    let current: Vec<i64> = std::iter::once(master).chain(stack).map(|n| n.id).collect();
+    let focused = tree
+        .find_focused_as_ref(tree::is_window)
+        .and_then(|f| current.iter().position(|id| *id == f.id));
+    let cmds = match command {
+        MasterCommand::Resize(step) => {
+            let (change, step) = if step < 0 {
+                ("shrink", -step)
+            } else {
+                ("grow", step)
+            };
+            vec![format!(
+                "[con_id={}] resize {} width {} ppt",
+                master.id, change, step
+            )]
+        }
+        _ => reorder(&current, &target(command, &current, focused)?),
+    };
+    let cmds: Vec<String> = side_by_side(ws).into_iter().chain(cmds).collect();
+    if !cmds.is_empty() {
+        connection::run_command(conn, cmds.join("; ")).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs the swap commands of `reorder` on `windows`.
+    fn apply(windows: &[i64], cmds: &[String]) -> Vec<i64> {
+        let mut windows = windows.to_vec();
+        for cmd in cmds {
+            let ids: Vec<i64> = cmd
+                .split(|c: char| !c.is_ascii_digit())
+                .filter_map(|n| n.parse().ok())
+                .collect();
+            let a = windows.iter().position(|w| *w == ids[0]).unwrap();
+            let b = windows.iter().position(|w| *w == ids[1]).unwrap();
+            windows.swap(a, b);
+        }
+        windows
+    }
+
+    fn moved(command: MasterCommand, current: &[i64], focused: usize) -> Vec<i64> {
+        let target = target(command, current, Some(focused)).unwrap();
+        assert_eq!(apply(current, &reorder(current, &target)), target);
+        target
+    }
+
+    const CW: MasterCommand = MasterCommand::Rotate { clockwise: true };
+    const CCW: MasterCommand = MasterCommand::Rotate { clockwise: false };
+
+    #[test]
+    fn reorder_reaches_any_order() {
+        let current = [1, 2, 3, 4];
+        for target in [[1, 2, 3, 4], [4, 3, 2, 1], [2, 3, 4, 1], [3, 1, 4, 2]] {
+            let cmds = reorder(&current, &target);
+            assert_eq!(apply(&current, &cmds), target);
+        }
+        assert!(reorder(&current, &current).is_empty());
+    }
+
+    #[test]
+    fn rotate_moves_every_window_one_place_on() {
+        assert_eq!(moved(CW, &[1, 2, 3], 0), vec![3, 1, 2]);
+        assert_eq!(moved(CCW, &[1, 2, 3], 0), vec![2, 3, 1]);
+        // a stack of one swaps with the master either way
+        assert_eq!(moved(CW, &[1, 2], 1), vec![2, 1]);
+        assert_eq!(moved(CCW, &[1, 2], 1), vec![2, 1]);
+    }
+
+    #[test]
+    fn promote_puts_the_focused_window_first() {
+        assert_eq!(
+            moved(MasterCommand::Promote, &[1, 2, 3, 4], 2),
+            vec![3, 1, 2, 4]
+        );
+        assert_eq!(
+            moved(MasterCommand::Promote, &[1, 2, 3, 4], 3),
+            vec![4, 1, 2, 3]
+        );
+        // with the master focused the top of the stack takes its place
+        assert_eq!(moved(MasterCommand::Promote, &[1, 2, 3], 0), vec![2, 1, 3]);
+        assert_eq!(moved(MasterCommand::Promote, &[1, 2], 0), vec![2, 1]);
+        assert_eq!(moved(MasterCommand::Promote, &[1, 2], 1), vec![2, 1]);
+    }
+
+    #[test]
+    fn swap_trades_places_with_the_master() {
+        assert_eq!(
+            moved(MasterCommand::Swap, &[1, 2, 3, 4], 2),
+            vec![3, 2, 1, 4]
+        );
+        assert_eq!(moved(MasterCommand::Swap, &[1, 2, 3], 0), vec![2, 1, 3]);
+        assert_eq!(moved(MasterCommand::Swap, &[1, 2], 1), vec![2, 1]);
+    }
+
+    #[test]
+    fn a_lone_master_stays_put() {
+        for command in [MasterCommand::Promote, MasterCommand::Swap, CW, CCW] {
+            assert_eq!(target(command, &[1], None).unwrap(), vec![1]);
+        }
+    }
+
+    #[test]
+    fn promote_needs_a_focused_window() {
+        assert!(target(MasterCommand::Promote, &[1, 2], None).is_err());
+        assert_eq!(target(CW, &[1, 2], None).unwrap(), vec![2, 1]);
+    }
+}
@@ -0,0 +1,169 @@
+mod alternating;
+mod master_stack;
+mod spiral;
+mod three_column;
+
+use crate::connection;
+use crate::tree;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+use swayipc_async::{Connection, Node, NodeLayout, WindowChange, WindowEvent};
+
+pub use alternating::Alternating;
+pub use master_stack::MasterCommand;
+
+const TAIL_MARK: &str = "_persway_tail";
+
+/// The automatic layouts persway can manage a workspace with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layout {
+    /// Leave the workspace alone.
+    None,
+    /// Split the focused window along its longest side, somewhat reminiscent of the Awesome WM.
+    #[default]
+    Alternating,
+    /// One big window on the left and a stack of the other windows on the right.
+    MasterStack,
+    /// Every new window takes half of the space of the focused one, alternating between
+    /// horizontal and vertical splits.
+    Dwindle,
+    /// Like dwindle, but the new windows spiral inwards.
+    Spiral,
+    /// Three columns side by side, extra windows are stacked in the emptiest column.
+    ThreeColumn,
+}
+
+impl Layout {
+    const ALL: [Layout; 6] = [
+        Layout::None,
+        Layout::Alternating,
+        Layout::MasterStack,
+        Layout::Dwindle,
+        Layout::Spiral,
+        Layout::ThreeColumn,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Layout::None => "none",
+            Layout::Alternating => "alternating",
+            Layout::MasterStack => "master-stack",
+            Layout::Dwindle => "dwindle",
+            Layout::Spiral => "spiral",
+            Layout::ThreeColumn => "three-column",
+        }
+    }
+
+    /// Reacts to a window event on workspace `ws`, which is part of `tree`.
+    pub async fn handle(
+        self,
+        event: &WindowEvent,
+        tree: &Node,
+        ws: &Node,
+        alternating: &Alternating,
+        conn: &mut Connection,
+    ) -> Result<()> {
+        match self {
+            Layout::None => Ok(()),
+            Layout::Alternating => alternating::handle(event, tree, alternating, conn).await,
+            Layout::Dwindle => spiral::handle(event, ws, false, conn).await,
+            Layout::Spiral => spiral::handle(event, ws, true, conn).await,
+            Layout::MasterStack | Layout::ThreeColumn => match event.change {
+                WindowChange::New | WindowChange::Move if tree::is_floating(&event.container) => {
+                    Ok(())
+                }
+                WindowChange::New | WindowChange::Close | WindowChange::Move => {
+                    self.arrange(ws, conn).await
+                }
+                _ => Ok(()),
+            },
+        }
+    }
+
+    /// Brings a workspace that just switched to this layout into shape, as far
+    /// as the layout cares about existing windows.
+    pub async fn arrange(self, ws: &Node, conn: &mut Connection) -> Result<()> {
+        match self {
+            Layout::MasterStack => master_stack::arrange(ws, conn).await,
+            Layout::ThreeColumn => three_column::arrange(ws, conn).await,
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Layout {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Layout::ALL
+            .iter()
+            .copied()
+            .find(|l| l.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = Layout::ALL.iter().map(|l| l.name()).collect();
+                anyhow!(
+                    "unknown layout '{}', expected one of {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// Rearranges the workspace `ws`, which is part of `tree`, see `MasterCommand`.
+pub async fn master(
+    command: MasterCommand,
+    tree: &Node,
+    ws: &Node,
+    conn: &mut Connection,
+) -> Result<()> {
+    master_stack::run(command, tree, ws, conn).await
+}
+
+/// Removes the mark used while stacking windows, which is left behind when
+/// persway stops halfway through.
+pub async fn unmark(conn: &mut Connection) -> Result<()> {
+    connection::run_command(conn, format!("unmark {}", TAIL_MARK)).await?;
+    Ok(())
+}
+
+/// The command putting the windows at the top of a workspace side by side,
+/// when they are not already, eg. on a portrait output. Criteria cannot pick
+/// a workspace, but `layout` on a window at the top of a workspace lays out
+/// the workspace.
+fn side_by_side(ws: &Node) -> Option<String> {
+    let first = ws.nodes.first()?;
+    (ws.layout != NodeLayout::SplitH).then(|| format!("[con_id={}] layout splith", first.id))
+}
+
+/// Commands moving `nodes` to the bottom of `column`, which is split first when
+/// it is a single window.
+fn stack_onto(column: &Node, nodes: &[&Node]) -> Vec<String> {
+    let mut cmds = vec![];
+    let tail = match column.nodes.last() {
+        Some(tail) => tail.id,
+        None => {
+            cmds.push(format!("[con_id={}] split v", column.id));
+            column.id
+        }
+    };
+    cmds.push(format!("[con_id={}] mark --add {}", tail, TAIL_MARK));
+    for node in nodes {
+        cmds.push(format!(
+            "[con_id={}] move container to mark {}",
+            node.id, TAIL_MARK
+        ));
+        cmds.push(format!("[con_id={}] mark --add {}", node.id, TAIL_MARK));
+    }
+    cmds.push(format!("unmark {}", TAIL_MARK));
+    cmds
+}
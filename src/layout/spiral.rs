@@ -0,0 +1,59 @@
+use crate::connection;
+use crate::tree;
+use anyhow::Result;
+use swayipc_async::{Connection, Node, NodeLayout, WindowChange, WindowEvent};
+
+/// Dwindle splits the focused window in the other direction than its parent,
+/// so every new window takes half of the space of the previous one. Spiral
+/// additionally moves every other pair of new windows to the opposite side, so
+/// they go right, down, left, up and so on.
+pub async fn handle(
+    event: &WindowEvent,
+    ws: &Node,
+    spiral: bool,
+    conn: &mut Connection,
+) -> Result<()> {
+    let (window, ancestors) = match tree::find_with_ancestors(ws, event.container.id) {
+        Some(found) => found,
+        None => return Ok(()),
+    };
+    let parent = match ancestors.first() {
+        Some(parent) => parent,
+        None => return Ok(()),
+    };
+    let is_tabbed_or_stacked = matches!(parent.layout, NodeLayout::Tabbed | NodeLayout::Stacked);
+    if tree::is_floating(window) || tree::is_full_screen(window) || is_tabbed_or_stacked {
+        return Ok(());
+    }
+
+    match event.change {
+        WindowChange::Focus => {
+            let split_horizontally = if parent.nodes.len() > 1 {
+                parent.layout == NodeLayout::SplitV
+            } else {
+                window.rect.width >= window.rect.height
+            };
+            let cmd = if split_horizontally {
+                "split h"
+            } else {
+                "split v"
+            };
+            connection::run_command(conn, format!("[con_id={}] {}", window.id, cmd)).await?;
+        }
+        WindowChange::New if spiral => {
+            // windows directly on the workspace have a depth of 1
+            let depth = ancestors.len();
+            let is_last = parent.nodes.last().map(|n| n.id) == Some(window.id);
+            if parent.nodes.len() == 2 && is_last && (depth.saturating_sub(1) / 2) % 2 == 1 {
+                let cmd = if parent.layout == NodeLayout::SplitV {
+                    "move up"
+                } else {
+                    "move left"
+                };
+                connection::run_command(conn, format!("[con_id={}] {}", window.id, cmd)).await?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
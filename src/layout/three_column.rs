@@ -0,0 +1,37 @@
+use super::{side_by_side, stack_onto};
+use crate::connection;
+use crate::tree;
+use anyhow::Result;
+use swayipc_async::{Connection, Node};
+
+const COLUMNS: usize = 3;
+
+/// Keeps the tiled windows of a workspace in three columns side by side. Once
+/// all columns are taken, new windows go to the bottom of the column with the
+/// fewest windows.
+pub async fn arrange(ws: &Node, conn: &mut Connection) -> Result<()> {
+    let mut cmds: Vec<String> = side_by_side(ws).into_iter().collect();
+    let (columns, extra) = ws.nodes.split_at(ws.nodes.len().min(COLUMNS));
+    let mut counts: Vec<usize> = columns.iter().map(tree::count_windows).collect();
+    let mut assigned: Vec<Vec<&Node>> = vec![vec![]; COLUMNS];
+    for node in extra {
+        let (fewest, _) = counts
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, count)| **count)
+            .unwrap_or((0, &0));
+        counts[fewest] += tree::count_windows(node);
+        assigned[fewest].push(node);
+    }
+    cmds.extend(
+        columns
+            .iter()
+            .zip(assigned)
+            .filter(|(_, nodes)| !nodes.is_empty())
+            .flat_map(|(column, nodes)| stack_onto(column, &nodes)),
+    );
+    if !cmds.is_empty() {
+        connection::run_command(conn, cmds.join("; ")).await?;
+    }
+    Ok(())
+}
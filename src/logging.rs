@@ -0,0 +1,46 @@
+use anyhow::{anyhow, Result};
+use env_logger::{Builder, Env};
+use log::LevelFilter;
+use serde_json::json;
+use std::io::Write;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line, for log collectors.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(anyhow!("expected text or json, got '{}'", s)),
+        }
+    }
+}
+
+/// Logs to stderr. The level comes from `level` if given, otherwise from
+/// RUST_LOG, and defaults to info.
+pub fn init(level: Option<LevelFilter>, format: LogFormat) {
+    let mut builder = Builder::from_env(Env::default().default_filter_or("info"));
+    if let Some(level) = level {
+        builder.filter_module(env!("CARGO_PKG_NAME"), level);
+    }
+    if format == LogFormat::Json {
+        builder.format(|buf, record| {
+            let line = json!({
+                "timestamp": buf.timestamp().to_string(),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            });
+            writeln!(buf, "{}", line)
+        });
+    }
+    builder.init();
+}
@@ -1,12 +1,27 @@
-use anyhow::{anyhow, Result};
-use async_std::prelude::*;
-use signal_hook::consts::signal::*;
-use signal_hook_async_std::Signals;
-use std::process::exit;
+mod config;
+mod connection;
+mod control;
+mod criteria;
+mod daemon;
+mod dim;
+mod exec;
+mod history;
+mod hooks;
+mod layout;
+mod logging;
+mod rename;
+mod scratchpad;
+mod swallow;
+mod template;
+mod tree;
+
+use anyhow::Result;
+use config::{Config, Hook};
+use layout::Layout;
+use log::LevelFilter;
+use logging::LogFormat;
+use std::path::PathBuf;
 use structopt::StructOpt;
-use swayipc_async::{
-    Connection, Event, EventType, NodeLayout, NodeType, WindowChange, WindowEvent, Workspace,
-};
 
 #[derive(StructOpt)]
 /// I am Persway. A friendly daemon.
@@ -18,10 +33,25 @@ struct Cli {
     /// somewhat reminiscent of the Awesome WM.
     #[structopt(short = "a", long = "autolayout")]
     autolayout: bool,
+    /// The layout used by autolayout, one of alternating (the default), master-stack,
+    /// dwindle, spiral or three-column.
+    #[structopt(long = "layout")]
+    layout: Option<Layout>,
     /// Enable automatic workspace renaming based on what is running
-    /// in the workspace (eg. application name).
+    /// in the workspace (eg. application name). Workspaces get their
+    /// old names back when persway exits.
     #[structopt(short = "w", long = "workspace-renaming")]
     workspace_renaming: bool,
+    /// Dim every window but the focused one, to 0.8 opacity unless set otherwise in the
+    /// [dimming] table of the config file. Fullscreen windows are left alone and windows
+    /// are made opaque again when persway exits.
+    #[structopt(short = "d", long = "dim")]
+    dim: bool,
+    /// Let windows started from a terminal take its place, hiding the terminal in the
+    /// scratchpad until they close. Which windows and terminals take part is set in the
+    /// [swallowing] table of the config file.
+    #[structopt(long = "swallow")]
+    swallow: bool,
     /// Called when window comes into focus. To automatically set the opacity of
     /// all other windows to 0.8 for example, you would set this to:
     ///
@@ -33,16 +63,46 @@ struct Cli {
     /// [tiling] opacity 0.8; [app_id="firefox"] opacity 1; opacity 1
     #[structopt(short = "f", long = "on-window-focus")]
     on_window_focus: Option<String>,
-    /// Called when window leaves focus. To automatically mark these for example, you would set
+    /// Called when window leaves focus, for that window. To dim it for example, you would set
     /// this to:
     ///
-    /// mark --add _prev
-    ///
-    /// and then in your sway config:
+    /// opacity 0.8
     ///
-    /// bindsym Mod1+tab [con_mark=_prev] focus
+    /// To go back to the previous window there is no need for marks, persway keeps a focus
+    /// history, see `persway msg focus-previous`.
     #[structopt(short = "l", long = "on-window-focus-leave")]
     on_window_focus_leave: Option<String>,
+    /// Called when a window is created. Like every on-window-* hook but on-window-close, its
+    /// commands apply to the window in question unless they have criteria of their own. To give new windows
+    /// a thin border for example:
+    ///
+    /// border pixel 2
+    #[structopt(long = "on-window-new")]
+    on_window_new: Option<String>,
+    /// Called when a window is closed. The window is gone by then, so unlike the other window
+    /// hooks this one is not scoped to it.
+    #[structopt(long = "on-window-close")]
+    on_window_close: Option<String>,
+    /// Called when a window is moved, eg. to another workspace.
+    #[structopt(long = "on-window-move")]
+    on_window_move: Option<String>,
+    /// Called when a window is made floating or tiling.
+    #[structopt(long = "on-window-floating")]
+    on_window_floating: Option<String>,
+    /// Called when a window enters or leaves fullscreen.
+    #[structopt(long = "on-window-fullscreen-mode")]
+    on_window_fullscreen_mode: Option<String>,
+    /// Called when the title of a window changes.
+    #[structopt(long = "on-window-title")]
+    on_window_title: Option<String>,
+    /// Called when a window becomes urgent or stops being urgent. To be told about it for example:
+    ///
+    /// exec notify-send "a window wants attention"
+    #[structopt(long = "on-window-urgent")]
+    on_window_urgent: Option<String>,
+    /// Called when the marks of a window change.
+    #[structopt(long = "on-window-mark")]
+    on_window_mark: Option<String>,
     /// Called when persway exits. This can be used to reset any opacity changes
     /// or other settings when persway exits. For example, if changing the opacity
     /// on window focus, you would probably want to reset that on exit like this:
@@ -52,162 +112,117 @@ struct Cli {
     /// Eg. set all tiling windows to opacity 1
     #[structopt(short = "e", long = "on-exit")]
     on_exit: Option<String>,
+    /// Check the command names and criteria of hooks with sway at startup, on reload and
+    /// when they are set over the control socket, without running them. Hooks with unknown
+    /// commands or broken criteria are refused, arguments are not checked. Either way sway
+    /// commands of a hook that fail when it runs are logged.
+    #[structopt(long = "validate-hooks")]
+    validate_hooks: bool,
+    /// When sway shuts down, wait for it to be started again rather than exiting. Either way
+    /// persway cleans up after itself and runs the on-exit hook first.
+    #[structopt(long = "wait-for-sway")]
+    wait_for_sway: bool,
+    /// Path of the config file. Defaults to $XDG_CONFIG_HOME/persway/config.toml. Options
+    /// given on the command line take precedence over the config file, which is re-read
+    /// when persway receives SIGHUP.
+    #[structopt(short = "c", long = "config", parse(from_os_str))]
+    config: Option<PathBuf>,
+    /// Only log messages of this level or above: error, warn, info, debug or trace. Overrides
+    /// RUST_LOG, the default is info. At debug level every event from sway and every command
+    /// sent to sway is logged.
+    #[structopt(long = "log-level")]
+    log_level: Option<LevelFilter>,
+    /// Log as text or as json, one object per line.
+    #[structopt(long = "log-format", default_value = "text")]
+    log_format: LogFormat,
+    /// Path of the control socket. Defaults to a socket in $XDG_RUNTIME_DIR named
+    /// after the sway socket, so every sway session gets its own persway.
+    #[structopt(short = "s", long = "socket-path", parse(from_os_str))]
+    socket_path: Option<PathBuf>,
+    #[structopt(subcommand)]
+    cmd: Option<Subcommand>,
 }
 
-async fn handle_signals(signals: Signals) {
-    let mut signals = signals.fuse();
-    let args = Cli::from_args();
-    let on_exit = args.on_exit;
-    while let Some(signal) = signals.next().await {
-        match signal {
-            SIGHUP | SIGINT | SIGQUIT | SIGTERM => {
-                let mut commands = Connection::new().await.unwrap();
-                if let Some(exit_cmd) = on_exit {
-                    commands.run_command(exit_cmd).await.unwrap();
-                }
-                exit(0)
-            }
-            _ => unreachable!(),
-        }
-    }
+#[derive(StructOpt)]
+enum Subcommand {
+    /// Send a command to a running persway over its control socket and print the reply.
+    ///
+    /// Available commands:
+    ///
+    /// autolayout [on|off|toggle]
+    ///
+    /// layout [workspace <num>|output <name>] <none|alternating|master-stack|dwindle|spiral|three-column>
+    ///
+    /// master <promote|swap|rotate <cw|ccw>|grow [ppt]|shrink [ppt]>
+    ///
+    /// workspace-renaming [on|off|toggle]
+    ///
+    /// dim [on|off|toggle]
+    ///
+    /// swallow [on|off|toggle]
+    ///
+    /// <hook> [sway command], where hook is any on-* option above or hook in the config file
+    ///
+    /// focus-previous [workspace] [n]
+    ///
+    /// switch <next|prev> [workspace]
+    ///
+    /// switch end
+    ///
+    /// history [workspace]
+    ///
+    /// toggle <scratchpad>
+    ///
+    /// status
+    ///
+    /// Hooks are cleared when no sway command is given. The focus history commands reply with
+    /// the windows they are about, toggle and master reply with nothing, every other command
+    /// replies with the current settings.
+    Msg {
+        #[structopt(required = true)]
+        command: Vec<String>,
+    },
 }
 
 #[async_std::main]
 async fn main() -> Result<()> {
     let args = Cli::from_args();
-    let on_window_focus = args.on_window_focus;
-    let on_window_focus_leave = args.on_window_focus_leave;
-
-    let signals = Signals::new(&[SIGHUP, SIGINT, SIGQUIT, SIGTERM])?;
-    let handle = signals.handle();
-    let signals_task = async_std::task::spawn(handle_signals(signals));
-
-    let mut commands = Connection::new().await?;
-    let subs = [EventType::Window];
-    let mut events = Connection::new().await?.subscribe(&subs).await?;
-    let mut prev = None;
-    while let Some(event) = events.next().await {
-        match event? {
-            Event::Window(event) => {
-                match event.change {
-                    WindowChange::Focus => {
-                        // run focus leave hook
-                        if let Some(window_focus_leave_cmd) = &on_window_focus_leave {
-                            if let Some(id) = prev {
-                                commands
-                                    .run_command(format!(
-                                        "[con_id={id}] {}",
-                                        window_focus_leave_cmd
-                                    ))
-                                    .await?;
-                            }
-                        }
-                        if let Some(window_focus_cmd) = &on_window_focus {
-                            commands.run_command(window_focus_cmd).await?;
-                        }
-                        if args.workspace_renaming {
-                            if let Err(e) = rename_workspace(&event, &mut commands).await {
-                                println!("workspace rename err: {}", e);
-                            }
-                        };
-
-                        if args.autolayout {
-                            if let Err(e) = autolayout(&mut commands).await {
-                                println!("autolayout err: {}", e);
-                            };
-                        };
-                        prev = Some(event.container.id);
-                    }
-                    WindowChange::Close => {
-                        // run focus leave hook
-                        if let Some(window_focus_leave_cmd) = &on_window_focus_leave {
-                            if let Some(id) = prev {
-                                commands
-                                    .run_command(format!(
-                                        "[con_id={id}] {}",
-                                        window_focus_leave_cmd
-                                    ))
-                                    .await?;
-                            }
-                        }
-                        if args.workspace_renaming {
-                            if let Err(e) = rename_workspace(&event, &mut commands).await {
-                                println!("workspace rename err: {}", e);
-                            }
-                        };
-                        prev = None;
-                    }
-                    _ => {}
-                }
-            }
-            _ => unreachable!(),
-        }
-    }
-
-    handle.close();
-    signals_task.await;
-    Ok(())
-}
-
-async fn autolayout(conn: &mut Connection) -> Result<()> {
-    let tree = conn.get_tree().await?;
-    let focused = tree
-        .find_focused_as_ref(|n| n.focused)
-        .ok_or_else(|| anyhow!("No focused node"))?;
-    let parent = tree
-        .find_focused_as_ref(|n| n.nodes.iter().any(|n| n.focused))
-        .ok_or_else(|| anyhow!("No parent"))?;
-    let is_floating = focused.node_type == NodeType::FloatingCon;
-    let is_full_screen = focused.percent.unwrap_or(1.0) > 1.0;
-    let is_stacked = parent.layout == NodeLayout::Stacked;
-    let is_tabbed = parent.layout == NodeLayout::Tabbed;
-    if !is_floating && !is_full_screen && !is_stacked && !is_tabbed {
-        let cmd = if focused.rect.height > focused.rect.width {
-            "split v"
-        } else {
-            "split h"
+    if let Some(Subcommand::Msg { command }) = args.cmd {
+        let socket_path = match args.socket_path {
+            Some(path) => path,
+            None => control::default_socket_path()?,
         };
-        conn.run_command(cmd).await?;
-    };
-
-    Ok(())
-}
-
-async fn get_focused_workspace(conn: &mut Connection) -> Result<Workspace> {
-    let mut ws = conn.get_workspaces().await?.into_iter();
-    ws.find(|w| w.focused)
-        .ok_or_else(|| anyhow!("No focused workspace"))
-}
-
-async fn rename_workspace(event: &WindowEvent, conn: &mut Connection) -> Result<()> {
-    let current_ws = get_focused_workspace(conn).await?;
-    let ws_num = current_ws
-        .name
-        .split(':')
-        .next()
-        .unwrap_or(&current_ws.name);
-
-    if current_ws.focus.is_empty() {
-        let cmd = format!("rename workspace to {}", ws_num);
-        conn.run_command(&cmd).await?;
+        let reply = control::send(&socket_path, &command.join(" ")).await?;
+        print!("{}", reply);
         return Ok(());
     }
 
-    let app_id = event.container.app_id.as_ref();
-    let window_properties = event.container.window_properties.as_ref();
-    let app_name = app_id.map_or_else(|| window_properties.and_then(|p| p.class.as_ref()), Some);
-
-    if let Some(app_name) = app_name {
-        let newname = format!(
-            "{}: {}",
-            ws_num,
-            app_name
-                .trim_start_matches('-')
-                .trim_end_matches('-')
-                .to_lowercase()
-        );
-        let cmd = format!("rename workspace to {}", newname);
-        conn.run_command(&cmd).await?;
+    logging::init(args.log_level, args.log_format);
+    let config_path = match args.config {
+        Some(path) => path,
+        None => config::default_config_path()?,
+    };
+    // flags can only switch things on, leave them unset otherwise so the config file decides
+    let cli = Config {
+        autolayout: args.autolayout.then_some(true),
+        layout: args.layout,
+        workspace_renaming: args.workspace_renaming.then_some(true),
+        dim: args.dim.then_some(true),
+        swallow: args.swallow.then_some(true),
+        on_window_focus: args.on_window_focus.map(Hook::Command),
+        on_window_focus_leave: args.on_window_focus_leave.map(Hook::Command),
+        on_window_new: args.on_window_new.map(Hook::Command),
+        on_window_close: args.on_window_close.map(Hook::Command),
+        on_window_move: args.on_window_move.map(Hook::Command),
+        on_window_floating: args.on_window_floating.map(Hook::Command),
+        on_window_fullscreen_mode: args.on_window_fullscreen_mode.map(Hook::Command),
+        on_window_title: args.on_window_title.map(Hook::Command),
+        on_window_urgent: args.on_window_urgent.map(Hook::Command),
+        on_window_mark: args.on_window_mark.map(Hook::Command),
+        on_exit: args.on_exit.map(Hook::Command),
+        validate_hooks: args.validate_hooks.then_some(true),
+        wait_for_sway: args.wait_for_sway.then_some(true),
+        ..Config::default()
     };
-    Ok(())
+    daemon::run(cli, config_path, args.socket_path).await
 }
@@ -0,0 +1,414 @@
+use crate::connection;
+use crate::criteria::{Criteria, Subject};
+use crate::hooks;
+use crate::template::{Segment, Template};
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::iter;
+use swayipc_async::{Connection, Node, NodeType, WindowChange, Workspace};
+
+/// Gives windows matching the criteria a fixed name and/or icon in workspace
+/// names instead of their app_id or class, eg. in the config file:
+///
+/// [[rename-rules]]
+/// match = { app-id = "^org\\.mozilla\\.firefox$" }
+/// name = "web"
+/// icon = ""
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RenameRule {
+    #[serde(rename = "match")]
+    pub criteria: Criteria,
+    pub name: Option<String>,
+    pub icon: Option<String>,
+}
+
+const PLACEHOLDERS: &[&str] = &["num", "app", "title", "count", "icons", "output"];
+
+/// The layout of workspace names, eg. `{num}: {app}`. The same template is used
+/// to find the number back in an already renamed workspace, so `{num}` is required.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct NameTemplate {
+    template: Template,
+    /// Finds a workspace number, as sway gives workspaces.
+    numbered: Regex,
+    /// Finds any workspace name, for workspaces named otherwise.
+    named: Regex,
+}
+
+impl TryFrom<String> for NameTemplate {
+    type Error = anyhow::Error;
+
+    fn try_from(source: String) -> Result<Self> {
+        let template = Template::parse(&source, PLACEHOLDERS)?;
+        if !template.contains("num") {
+            return Err(anyhow!(
+                "workspace name template '{}' has no {{num}}",
+                source
+            ));
+        }
+        let segments = template.segments();
+        let num_at = segments
+            .iter()
+            .position(|s| matches!(s, Segment::Placeholder(p) if p == "num"))
+            .unwrap_or_default();
+        let last = !segments[num_at + 1..]
+            .iter()
+            .any(|s| matches!(s, Segment::Placeholder(_)));
+        let pattern = |num: &str, before: &str| {
+            let mut pattern = String::from("^");
+            for (i, segment) in segments.iter().enumerate() {
+                match segment {
+                    Segment::Literal(literal) => pattern.push_str(&regex::escape(literal)),
+                    Segment::Placeholder(_) if i == num_at => {
+                        pattern.push_str(&format!("(?P<num>{})", num))
+                    }
+                    Segment::Placeholder(_) if i < num_at => pattern.push_str(before),
+                    Segment::Placeholder(_) => pattern.push_str(".*?"),
+                }
+            }
+            pattern.push('$');
+            Regex::new(&pattern)
+        };
+        // Application names and titles may contain anything, including the
+        // literals around `{num}`. A number is taken to be the first one that
+        // fits, names are taken to be the last one when nothing follows them.
+        let named_before = if last { ".*" } else { ".*?" };
+        Ok(NameTemplate {
+            numbered: pattern(r"\d+", ".*?")?,
+            named: pattern(".*?", named_before)?,
+            template,
+        })
+    }
+}
+
+impl NameTemplate {
+    /// The `{num}` part of a workspace name made from this template, or the
+    /// whole name for workspaces that have not been renamed.
+    pub fn num<'a>(&self, name: &'a str) -> &'a str {
+        [&self.numbered, &self.named]
+            .iter()
+            .find_map(|pattern| pattern.captures(name)?.name("num"))
+            .map_or(name, |m| m.as_str())
+    }
+}
+
+impl Default for NameTemplate {
+    fn default() -> Self {
+        NameTemplate::try_from("{num}: {app}".to_string()).unwrap()
+    }
+}
+
+/// Which windows make up the name of a workspace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Windows {
+    /// Only the focused window.
+    Focused,
+    /// Every window on the workspace, each application only once.
+    All,
+}
+
+/// The order of the windows in a workspace name when naming after all windows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Order {
+    /// Left to right and top to bottom as laid out, floating windows last.
+    Position,
+    /// Most recently focused first.
+    Focus,
+}
+
+/// How workspaces are named, the `[renaming]` table in the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Renaming {
+    pub windows: Windows,
+    pub order: Order,
+    /// Put between application names when naming after all windows.
+    pub separator: String,
+    /// Application names are cut off (with an ellipsis) beyond this many characters.
+    pub max_length: Option<usize>,
+    /// The workspace name, with placeholders for the workspace number `{num}`, the
+    /// application names `{app}`, their icons `{icons}`, the focused window title
+    /// `{title}`, the number of windows `{count}` and the output name `{output}`.
+    pub template: NameTemplate,
+}
+
+impl Default for Renaming {
+    fn default() -> Self {
+        Renaming {
+            windows: Windows::Focused,
+            order: Order::Position,
+            separator: " ".to_string(),
+            max_length: None,
+            template: NameTemplate::default(),
+        }
+    }
+}
+
+impl Renaming {
+    /// Whether a change to a window other than focus or close can change the
+    /// name of the focused workspace.
+    pub fn renames_on(&self, change: &WindowChange) -> bool {
+        match change {
+            WindowChange::Title => self.template.template.contains("title"),
+            WindowChange::Move => {
+                self.windows == Windows::All || self.template.template.contains("count")
+            }
+            _ => false,
+        }
+    }
+}
+
+async fn get_focused_workspace(conn: &mut Connection) -> Result<Workspace> {
+    let mut ws = conn.get_workspaces().await?.into_iter();
+    ws.find(|w| w.focused)
+        .ok_or_else(|| anyhow!("No focused workspace"))
+}
+
+/// The names workspaces had before persway renamed them, by workspace id.
+#[derive(Debug, Default)]
+pub struct OriginalNames(HashMap<i64, String>);
+
+impl OriginalNames {
+    /// Remembers the name of a workspace about to be renamed, unless persway
+    /// renamed it before.
+    fn record(&mut self, ws: &Workspace) {
+        self.0.entry(ws.id).or_insert_with(|| ws.name.clone());
+    }
+
+    /// Gives every workspace persway renamed and which is still there its old name back.
+    pub async fn restore(&mut self, conn: &mut Connection) -> Result<()> {
+        let workspaces = conn.get_workspaces().await?;
+        let cmds: Vec<String> = self
+            .0
+            .drain()
+            .filter_map(|(id, name)| {
+                let ws = workspaces.iter().find(|ws| ws.id == id)?;
+                Some(format!(
+                    "rename workspace {} to {}",
+                    quote(&ws.name),
+                    quote(&name)
+                ))
+                .filter(|_| ws.name != name)
+            })
+            .collect();
+        hooks::run(conn, "workspace renaming", &cmds.join("; ")).await
+    }
+}
+
+/// Renames the focused workspace, `window` being the window that has focus, or
+/// had it until it closed.
+pub async fn rename_workspace(
+    window: &Node,
+    conn: &mut Connection,
+    renaming: &Renaming,
+    rules: &[RenameRule],
+    originals: &mut OriginalNames,
+) -> Result<()> {
+    let current_ws = get_focused_workspace(conn).await?;
+    let template = &renaming.template;
+    let ws_num = template.num(&current_ws.name);
+
+    if current_ws.focus.is_empty() {
+        if current_ws.name != ws_num {
+            originals.record(&current_ws);
+            let cmd = format!("rename workspace to {}", quote(ws_num));
+            connection::run_command(conn, &cmd).await?;
+        }
+        return Ok(());
+    }
+
+    let tree = if renaming.windows == Windows::All || template.template.contains("count") {
+        Some(conn.get_tree().await?)
+    } else {
+        None
+    };
+    let ws_windows = match &tree {
+        Some(tree) => {
+            let ws = tree
+                .find_as_ref(|n| n.id == current_ws.id)
+                .ok_or_else(|| anyhow!("No workspace node"))?;
+            windows(ws, renaming.order)
+        }
+        None => vec![],
+    };
+    let named = match renaming.windows {
+        Windows::Focused => vec![window],
+        Windows::All => ws_windows.clone(),
+    };
+
+    let app_names = unique(named.iter().filter_map(|w| app_name(w, &current_ws, rules)));
+    if app_names.is_empty() {
+        return Ok(());
+    }
+    let icons = unique(named.iter().filter_map(|w| icon(w, &current_ws, rules)));
+    let newname = template.template.render(|placeholder| match placeholder {
+        "num" => ws_num.to_string(),
+        "app" => truncate(app_names.join(&renaming.separator), renaming.max_length),
+        "icons" => truncate(icons.join(&renaming.separator), renaming.max_length),
+        "title" => window.name.clone().unwrap_or_default(),
+        "count" => ws_windows.len().to_string(),
+        "output" => current_ws.output.clone(),
+        _ => unreachable!(),
+    });
+    if newname == current_ws.name {
+        return Ok(());
+    }
+    originals.record(&current_ws);
+    let cmd = format!("rename workspace to {}", quote(&newname));
+    connection::run_command(conn, &cmd).await?;
+    Ok(())
+}
+
+fn unique(names: impl Iterator<Item = String>) -> Vec<String> {
+    let mut unique = Vec::new();
+    for name in names {
+        if !unique.contains(&name) {
+            unique.push(name);
+        }
+    }
+    unique
+}
+
+/// Quotes a workspace name for a sway command, window titles may contain anything.
+pub fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// All windows below `node`, in the given order.
+fn windows(node: &Node, order: Order) -> Vec<&Node> {
+    let mut children: Vec<&Node> = node.nodes.iter().chain(&node.floating_nodes).collect();
+    if children.is_empty() {
+        return match node.node_type {
+            NodeType::Con | NodeType::FloatingCon => vec![node],
+            _ => vec![],
+        };
+    }
+    if order == Order::Focus {
+        children.sort_by_key(|c| {
+            node.focus
+                .iter()
+                .position(|id| *id == c.id)
+                .unwrap_or(usize::MAX)
+        });
+    }
+    children
+        .into_iter()
+        .flat_map(|c| windows(c, order))
+        .collect()
+}
+
+fn truncate(name: String, max_length: Option<usize>) -> String {
+    match max_length {
+        Some(max) if name.chars().count() > max => name
+            .chars()
+            .take(max.saturating_sub(1))
+            .chain(iter::once('…'))
+            .collect(),
+        _ => name,
+    }
+}
+
+fn subject<'a>(node: &'a Node, ws: &'a Workspace) -> Subject<'a> {
+    Subject {
+        workspace: Some(&ws.name),
+        output: Some(&ws.output),
+        ..Subject::window(node)
+    }
+}
+
+/// The name a window contributes to its workspace name. The first matching
+/// rule with a name wins, otherwise it is the app_id or X11 class of the window.
+fn app_name(node: &Node, ws: &Workspace, rules: &[RenameRule]) -> Option<String> {
+    let rule_name = rules
+        .iter()
+        .filter(|r| r.criteria.matches(&subject(node, ws)))
+        .find_map(|r| r.name.clone());
+    if rule_name.is_some() {
+        return rule_name;
+    }
+    let app_id = node.app_id.as_ref();
+    let window_properties = node.window_properties.as_ref();
+    let app_name = app_id.map_or_else(|| window_properties.and_then(|p| p.class.as_ref()), Some);
+    app_name.map(|name| {
+        name.trim_start_matches('-')
+            .trim_end_matches('-')
+            .to_lowercase()
+    })
+}
+
+/// The icon a window contributes to `{icons}`, falling back to its name.
+fn icon(node: &Node, ws: &Workspace, rules: &[RenameRule]) -> Option<String> {
+    rules
+        .iter()
+        .filter(|r| r.criteria.matches(&subject(node, ws)))
+        .find_map(|r| r.icon.clone())
+        .or_else(|| app_name(node, ws, rules))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &NameTemplate, num: &str) -> String {
+        template.template.render(|placeholder| match placeholder {
+            "num" => num.to_string(),
+            "app" => "web firefox: 2|x".to_string(),
+            "icons" => "  ".to_string(),
+            "title" => "notes 2 - vim | 3".to_string(),
+            "count" => "2".to_string(),
+            _ => "eDP-1".to_string(),
+        })
+    }
+
+    #[test]
+    fn num_finds_the_number_back() {
+        for source in [
+            "{num}: {app}",
+            "{app} {num}",
+            "{num}",
+            "{icons} {num} {title}",
+            "{app}|{num}|{count}",
+            "{output}:{num}:{app}",
+            "[{num}] {title} ({count})",
+        ] {
+            let template = NameTemplate::try_from(source.to_string()).unwrap();
+            for num in ["1", "10"] {
+                let name = render(&template, num);
+                assert_eq!(template.num(&name), num, "{} rendered as {}", source, name);
+                // renaming again keeps the name as it is
+                let again = render(&template, template.num(&name));
+                assert_eq!(again, name, "{}", source);
+            }
+        }
+    }
+
+    #[test]
+    fn num_finds_names_back() {
+        for source in [
+            "{num}: {app}",
+            "{app} {num}",
+            "{icons} {num}",
+            "{num} {app} {title}",
+        ] {
+            let template = NameTemplate::try_from(source.to_string()).unwrap();
+            for num in ["mail", "1:mail"] {
+                let name = render(&template, num);
+                assert_eq!(template.num(&name), num, "{} rendered as {}", source, name);
+            }
+        }
+    }
+
+    #[test]
+    fn num_leaves_names_not_made_from_the_template() {
+        let template = NameTemplate::default();
+        assert_eq!(template.num("3"), "3");
+        assert_eq!(template.num("mail"), "mail");
+    }
+}
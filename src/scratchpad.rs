@@ -0,0 +1,190 @@
+//! Named scratchpads. Sway has a single scratchpad, persway keeps track of
+//! which window in it belongs to which name, starts the application when there
+//! is no window yet and remembers where a window was when it was hidden.
+use crate::criteria::{Criteria, Subject};
+use crate::hooks;
+use crate::tree;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+use swayipc_async::{Connection, Node, Rect};
+
+/// The workspace sway keeps hidden scratchpad windows on.
+const SCRATCH_WORKSPACE: &str = "__i3_scratch";
+/// Toggling a scratchpad again within this long after starting its application
+/// does not start it again, the window may just not be there yet.
+const LAUNCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A named scratchpad, eg. in the config file:
+///
+/// [scratchpads.term]
+/// match = { app-id = "^scratch-term$" }
+/// exec = "foot --app-id scratch-term"
+/// size = "80 ppt 60 ppt"
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scratchpad {
+    /// The window of the scratchpad is the one matching these.
+    #[serde(rename = "match")]
+    pub criteria: Criteria,
+    /// Started with sway's exec when there is no window yet.
+    pub exec: Option<String>,
+    /// The size of the window the first time it is shown, anything sway's
+    /// `resize set` takes.
+    pub size: Option<String>,
+}
+
+/// Where a window was on its workspace.
+#[derive(Debug, Clone, Copy)]
+struct Geometry {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+impl Geometry {
+    fn relative(rect: &Rect, ws: &Rect) -> Self {
+        Geometry {
+            x: rect.x - ws.x,
+            y: rect.y - ws.y,
+            width: rect.width,
+            height: rect.height,
+        }
+    }
+}
+
+/// The windows of the scratchpads and what persway knows about them.
+#[derive(Debug, Default)]
+pub struct Scratchpads {
+    windows: HashMap<String, i64>,
+    geometry: HashMap<String, Geometry>,
+    /// The scratchpad whose application was started last, to show its window when it turns up.
+    launched: Option<(String, Instant)>,
+}
+
+impl Scratchpads {
+    /// Shows the window of a scratchpad, hides it when it has focus, or starts
+    /// its application when there is no window.
+    pub async fn toggle(
+        &mut self,
+        conn: &mut Connection,
+        scratchpads: &BTreeMap<String, Scratchpad>,
+        name: &str,
+    ) -> Result<()> {
+        let scratchpad = scratchpads
+            .get(name)
+            .ok_or_else(|| anyhow!("no scratchpad named '{}'", name))?;
+        let tree = conn.get_tree().await?;
+        let window = match self.window(&tree, name, scratchpad) {
+            Some(window) => window,
+            None => return self.launch(conn, name, scratchpad).await,
+        };
+        let ws = tree::workspace_of(&tree, window.id).ok_or_else(|| anyhow!("No workspace"))?;
+        let hidden = ws.name.as_deref() == Some(SCRATCH_WORKSPACE);
+        let here = tree::focused_workspace(&tree).map(|ws| ws.id) == Some(ws.id);
+        let cmd = if hidden {
+            self.show(name, scratchpad)
+        } else if window.focused {
+            self.geometry
+                .insert(name.to_string(), Geometry::relative(&window.rect, &ws.rect));
+            "move scratchpad".to_string()
+        } else if here {
+            "focus".to_string()
+        } else {
+            // shown on another workspace, bring it over
+            format!("move scratchpad, {}", self.show(name, scratchpad))
+        };
+        let cmd = format!("[con_id={}] {}", window.id, cmd);
+        hooks::run(conn, "scratchpad", &cmd).await
+    }
+
+    /// Takes a new window into the scratchpad it matches, showing it right away
+    /// if it was started by toggling the scratchpad. Returns whether it did.
+    pub async fn capture(
+        &mut self,
+        conn: &mut Connection,
+        scratchpads: &BTreeMap<String, Scratchpad>,
+        window: &Node,
+    ) -> Result<bool> {
+        let subject = Subject::window(window);
+        let (name, scratchpad) = match scratchpads
+            .iter()
+            .find(|(_, s)| s.criteria.matches(&subject))
+        {
+            Some(found) => found,
+            None => return Ok(false),
+        };
+        let mut cmd = "move scratchpad".to_string();
+        match self.launched.take() {
+            Some((launched, _)) if launched == *name => {
+                cmd = format!("{}, {}", cmd, self.show(name, scratchpad));
+            }
+            other => self.launched = other,
+        }
+        self.windows.insert(name.clone(), window.id);
+        let cmd = format!("[con_id={}] {}", window.id, cmd);
+        hooks::run(conn, "scratchpad", &cmd).await?;
+        Ok(true)
+    }
+
+    pub fn forget(&mut self, id: i64) {
+        self.windows.retain(|_, w| *w != id);
+    }
+
+    /// The window of a scratchpad, the one persway took in if it is still
+    /// there, otherwise any matching window, eg. one started before persway.
+    fn window<'a>(
+        &mut self,
+        tree: &'a Node,
+        name: &str,
+        scratchpad: &Scratchpad,
+    ) -> Option<&'a Node> {
+        let window = self
+            .windows
+            .get(name)
+            .and_then(|id| tree.find_as_ref(|n| n.id == *id))
+            .or_else(|| {
+                tree.find_as_ref(|n| {
+                    tree::is_window(n) && scratchpad.criteria.matches(&Subject::window(n))
+                })
+            })?;
+        self.windows.insert(name.to_string(), window.id);
+        Some(window)
+    }
+
+    async fn launch(
+        &mut self,
+        conn: &mut Connection,
+        name: &str,
+        scratchpad: &Scratchpad,
+    ) -> Result<()> {
+        let exec = scratchpad
+            .exec
+            .as_ref()
+            .ok_or_else(|| anyhow!("no window for scratchpad '{}' and nothing to exec", name))?;
+        if let Some((launched, at)) = &self.launched {
+            if launched == name && at.elapsed() < LAUNCH_TIMEOUT {
+                return Ok(());
+            }
+        }
+        self.launched = Some((name.to_string(), Instant::now()));
+        hooks::run(conn, "scratchpad", &format!("exec {}", exec)).await
+    }
+
+    /// The commands showing a hidden scratchpad window where it was last, or in
+    /// the middle at the configured size the first time.
+    fn show(&self, name: &str, scratchpad: &Scratchpad) -> String {
+        match (self.geometry.get(name), &scratchpad.size) {
+            (Some(g), _) => format!(
+                "scratchpad show, resize set {} px {} px, move position {} px {} px",
+                g.width, g.height, g.x, g.y
+            ),
+            (None, Some(size)) => {
+                format!("scratchpad show, resize set {}, move position center", size)
+            }
+            (None, None) => "scratchpad show".to_string(),
+        }
+    }
+}
@@ -0,0 +1,253 @@
+//! Window swallowing: a window started from a terminal takes the place of the
+//! terminal, which is hidden in the scratchpad until the window closes. Which
+//! terminal started a window is found by walking up its parent processes.
+use crate::criteria::{Criteria, Subject};
+use crate::hooks;
+use crate::rename;
+use crate::tree;
+use anyhow::Result;
+use log::debug;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use swayipc_async::{Connection, Node};
+
+const SWALLOW_MARK: &str = "_persway_swallow";
+/// Terminals which are swallowed unless `terminals` says otherwise, by app_id
+/// or X11 class.
+const TERMINALS: &[&str] = &[
+    "foot",
+    "footclient",
+    "Alacritty",
+    "kitty",
+    "org.wezfurlong.wezterm",
+    "com.mitchellh.ghostty",
+    "XTerm",
+    "URxvt",
+];
+
+/// Which windows swallow which, the `[swallowing]` table in the config file, eg:
+///
+/// [swallowing]
+/// terminals = [{ app-id = "^foot$" }]
+/// exclude = [{ app-id = "^(mpv|imv)$" }]
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Swallowing {
+    /// The windows that can be swallowed, well known terminals when not given.
+    pub terminals: Option<Vec<Criteria>>,
+    /// Only windows matching one of these swallow their terminal, when given.
+    pub include: Vec<Criteria>,
+    /// Windows matching any of these never swallow their terminal.
+    pub exclude: Vec<Criteria>,
+}
+
+impl Swallowing {
+    fn is_terminal(&self, window: &Node) -> bool {
+        let subject = Subject::window(window);
+        match &self.terminals {
+            Some(terminals) => terminals.iter().any(|c| c.matches(&subject)),
+            None => [subject.app_id, subject.class]
+                .iter()
+                .flatten()
+                .any(|name| TERMINALS.contains(name)),
+        }
+    }
+
+    fn swallows(&self, window: &Node) -> bool {
+        let subject = Subject::window(window);
+        (self.include.is_empty() || self.include.iter().any(|c| c.matches(&subject)))
+            && !self.exclude.iter().any(|c| c.matches(&subject))
+    }
+}
+
+/// The parent of a process, from /proc/<pid>/stat.
+fn parent(pid: i32) -> Option<i32> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // the command name comes in parentheses and may contain anything, the
+    // state and the parent pid follow it
+    let (_, rest) = stat.rsplit_once(')')?;
+    rest.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Where a window is, to put another one in its place later.
+#[derive(Debug, Clone, Copy)]
+struct Place {
+    workspace: i64,
+    /// A window next to it, with whether it comes before that window.
+    neighbour: Option<(i64, bool)>,
+}
+
+impl Place {
+    fn of(tree: &Node, window: i64) -> Option<Self> {
+        let (_, ancestors) = tree::find_with_ancestors(tree, window)?;
+        let siblings = &ancestors.first()?.nodes;
+        let i = siblings.iter().position(|n| n.id == window)?;
+        let neighbour = match i {
+            0 => siblings.get(1).map(|n| (n.id, true)),
+            i => Some((siblings[i - 1].id, false)),
+        };
+        Some(Place {
+            workspace: tree::workspace_of(tree, window)?.id,
+            neighbour,
+        })
+    }
+
+    /// The commands putting a window shown from the scratchpad here, as far as
+    /// the neighbour and the workspace are still around.
+    fn commands(&self, tree: &Node, window: i64) -> Vec<String> {
+        let mut cmds = vec![];
+        let neighbour = self
+            .neighbour
+            .filter(|(id, _)| tree.find_as_ref(|n| n.id == *id).is_some());
+        let workspace = match neighbour {
+            Some((id, before)) => {
+                cmds.push(format!(
+                    "[con_id={n}] mark --add {mark}; [con_id={w}] move container to mark {mark}; \
+                     unmark {mark}",
+                    n = id,
+                    w = window,
+                    mark = SWALLOW_MARK
+                ));
+                // moving to a mark puts the window after the marked one
+                if before {
+                    cmds.push(format!(
+                        "[con_id={}] swap container with con_id {}",
+                        window, id
+                    ));
+                }
+                tree::workspace_of(tree, id)
+            }
+            None => {
+                let workspace = tree.find_as_ref(|n| n.id == self.workspace);
+                if let Some(name) = workspace.and_then(|ws| ws.name.as_deref()) {
+                    cmds.push(format!(
+                        "[con_id={}] move container to workspace {}",
+                        window,
+                        rename::quote(name)
+                    ));
+                }
+                workspace
+            }
+        };
+        let here = tree::focused_workspace(tree).map(|ws| ws.id);
+        if workspace.is_some_and(|ws| Some(ws.id) == here) {
+            cmds.push(format!("[con_id={}] focus", window));
+        }
+        cmds
+    }
+}
+
+/// A terminal hidden away and where the window that swallowed it is.
+#[derive(Debug)]
+struct Swallowed {
+    terminal: i64,
+    place: Option<Place>,
+}
+
+impl Swallowed {
+    /// The commands bringing the terminal back where the window was.
+    fn release(&self, tree: &Node) -> String {
+        let t = self.terminal;
+        let mut cmds = vec![format!(
+            "[con_id={t}] scratchpad show; [con_id={t}] floating disable",
+            t = t
+        )];
+        if let Some(place) = &self.place {
+            cmds.extend(place.commands(tree, t));
+        }
+        cmds.join("; ")
+    }
+}
+
+/// The terminals hidden away, by the window that swallowed them.
+#[derive(Debug, Default)]
+pub struct Swallower {
+    swallowed: HashMap<i64, Swallowed>,
+}
+
+impl Swallower {
+    /// Lets a new window take the place of the terminal it was started from, if any.
+    pub async fn swallow(
+        &mut self,
+        conn: &mut Connection,
+        swallowing: &Swallowing,
+        window: &Node,
+    ) -> Result<()> {
+        let pid = match window.pid {
+            Some(pid) if !tree::is_floating(window) && swallowing.swallows(window) => pid,
+            _ => return Ok(()),
+        };
+        let tree = conn.get_tree().await?;
+        let terminals: HashMap<i32, &Node> = tree::windows(&tree)
+            .into_iter()
+            .filter(|n| n.id != window.id && !tree::is_floating(n))
+            .filter(|n| swallowing.is_terminal(n))
+            .filter_map(|n| Some((n.pid?, n)))
+            .collect();
+        if terminals.is_empty() {
+            return Ok(());
+        }
+        let mut ancestor = parent(pid);
+        let terminal = loop {
+            match ancestor {
+                Some(pid) if pid > 1 => match terminals.get(&pid) {
+                    Some(terminal) => break terminal,
+                    None => ancestor = parent(pid),
+                },
+                _ => return Ok(()),
+            }
+        };
+        debug!("window {} swallows terminal {}", window.id, terminal.id);
+        let cmds = format!(
+            "[con_id={t}] mark --add {mark}; [con_id={w}] move container to mark {mark}; \
+             unmark {mark}; [con_id={t}] move scratchpad; [con_id={w}] focus",
+            t = terminal.id,
+            w = window.id,
+            mark = SWALLOW_MARK
+        );
+        let swallowed = Swallowed {
+            terminal: terminal.id,
+            place: None,
+        };
+        self.swallowed.insert(window.id, swallowed);
+        hooks::run(conn, "swallowing", &cmds).await?;
+        self.moved(conn, window.id).await
+    }
+
+    /// Keeps track of where a window that swallowed a terminal is.
+    pub async fn moved(&mut self, conn: &mut Connection, window: i64) -> Result<()> {
+        if !self.swallowed.contains_key(&window) {
+            return Ok(());
+        }
+        let tree = conn.get_tree().await?;
+        if let Some(swallowed) = self.swallowed.get_mut(&window) {
+            swallowed.place = Place::of(&tree, window);
+        }
+        Ok(())
+    }
+
+    /// Brings back the terminal a closed window swallowed, where the window was.
+    pub async fn release(&mut self, conn: &mut Connection, window: i64) -> Result<()> {
+        let swallowed = match self.swallowed.remove(&window) {
+            Some(swallowed) => swallowed,
+            None => return Ok(()),
+        };
+        let tree = conn.get_tree().await?;
+        hooks::run(conn, "swallowing", &swallowed.release(&tree)).await
+    }
+
+    /// Brings back every swallowed terminal.
+    pub async fn restore(&mut self, conn: &mut Connection) -> Result<()> {
+        if self.swallowed.is_empty() {
+            return Ok(());
+        }
+        let tree = conn.get_tree().await?;
+        let cmds: Vec<String> = self
+            .swallowed
+            .drain()
+            .map(|(_, s)| s.release(&tree))
+            .collect();
+        hooks::run(conn, "swallowing", &cmds.join("; ")).await
+    }
+}
@@ -0,0 +1,83 @@
+use anyhow::{anyhow, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Literal(String),
+    Placeholder(String),
+}
+
+/// A string with `{name}` placeholders. Use `{{` and `}}` for literal braces.
+#[derive(Debug, Clone)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    /// Parses `source`, failing on placeholders that are not in `known`.
+    pub fn parse(source: &str, known: &[&str]) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = source.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let name: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                    if !known.contains(&name.as_str()) {
+                        return Err(anyhow!(
+                            "unknown placeholder {{{}}} in '{}', expected one of {}",
+                            name,
+                            source,
+                            known
+                                .iter()
+                                .map(|k| format!("{{{}}}", k))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ));
+                    }
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Placeholder(name));
+                }
+                '}' => return Err(anyhow!("unmatched }} in '{}'", source)),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Template { segments })
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    pub fn contains(&self, placeholder: &str) -> bool {
+        self.segments
+            .iter()
+            .any(|s| matches!(s, Segment::Placeholder(p) if p == placeholder))
+    }
+
+    /// Fills in every placeholder with what `value` returns for its name.
+    pub fn render<F>(&self, value: F) -> String
+    where
+        F: Fn(&str) -> String,
+    {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(literal) => literal.clone(),
+                Segment::Placeholder(name) => value(name),
+            })
+            .collect()
+    }
+}
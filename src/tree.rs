@@ -0,0 +1,94 @@
+//! Helpers for walking the node tree returned by `get_tree`.
+use swayipc_async::{Node, NodeType};
+
+/// Windows are the leaves of the tree, workspaces without windows are leaves too.
+pub fn is_window(node: &Node) -> bool {
+    node.nodes.is_empty()
+        && node.floating_nodes.is_empty()
+        && matches!(node.node_type, NodeType::Con | NodeType::FloatingCon)
+}
+
+pub fn count_windows(node: &Node) -> usize {
+    if is_window(node) {
+        return 1;
+    }
+    node.nodes
+        .iter()
+        .chain(&node.floating_nodes)
+        .map(count_windows)
+        .sum()
+}
+
+/// Every window in the node, tiling and floating.
+pub fn windows(node: &Node) -> Vec<&Node> {
+    if is_window(node) {
+        return vec![node];
+    }
+    node.nodes
+        .iter()
+        .chain(&node.floating_nodes)
+        .flat_map(windows)
+        .collect()
+}
+
+pub fn is_floating(node: &Node) -> bool {
+    node.node_type == NodeType::FloatingCon
+}
+
+/// Fullscreen on its workspace (1) or across every output (2).
+pub fn is_full_screen(node: &Node) -> bool {
+    matches!(node.fullscreen_mode, Some(1 | 2))
+}
+
+/// The node with the given id and the chain of its ancestors, closest first.
+pub fn find_with_ancestors(root: &Node, id: i64) -> Option<(&Node, Vec<&Node>)> {
+    if root.id == id {
+        return Some((root, vec![]));
+    }
+    root.nodes
+        .iter()
+        .chain(&root.floating_nodes)
+        .find_map(|child| find_with_ancestors(child, id))
+        .map(|(node, mut ancestors)| {
+            ancestors.push(root);
+            (node, ancestors)
+        })
+}
+
+/// The workspace the node with the given id is on.
+pub fn workspace_of(root: &Node, id: i64) -> Option<&Node> {
+    let (node, ancestors) = find_with_ancestors(root, id)?;
+    if node.node_type == NodeType::Workspace {
+        return Some(node);
+    }
+    ancestors
+        .into_iter()
+        .find(|n| n.node_type == NodeType::Workspace)
+}
+
+/// Every workspace along with the output it is on.
+pub fn workspaces(root: &Node) -> Vec<(&Node, &Node)> {
+    root.nodes
+        .iter()
+        .filter(|n| n.node_type == NodeType::Output)
+        .flat_map(|output| {
+            output
+                .nodes
+                .iter()
+                .filter(|n| n.node_type == NodeType::Workspace)
+                .map(move |ws| (output, ws))
+        })
+        .collect()
+}
+
+/// The output the workspace with the given id is on.
+pub fn output_of(root: &Node, ws_id: i64) -> Option<&Node> {
+    workspaces(root)
+        .into_iter()
+        .find(|(_, ws)| ws.id == ws_id)
+        .map(|(output, _)| output)
+}
+
+pub fn focused_workspace(root: &Node) -> Option<&Node> {
+    root.find_focused_as_ref(|n| n.node_type == NodeType::Workspace)
+}